@@ -0,0 +1,34 @@
+in vec2 TexCoords;
+in vec3 FragPos;
+in vec3 Normal;
+
+#include "lights.glsl"
+
+uniform sampler2D sprite;
+uniform bool lit;
+
+out vec4 FragColor;
+
+void main()
+{
+  vec4 color = texture(sprite, TexCoords);
+  if (color.a < 0.1) {
+    discard;
+  }
+
+  if (!lit) {
+    FragColor = color;
+    return;
+  }
+
+  // Light both sides the same, as the card is only an impression of a
+  // rounder shape
+  vec3 normal = normalize(Normal);
+  vec3 light = vec3(0.);
+  for (int i = 0; i < dir_lights_len; ++i) {
+    float diff = abs(dot(normal, normalize(-dir_lights[i].direction)));
+    light += dir_lights[i].ambient + dir_lights[i].diffuse * diff;
+  }
+
+  FragColor = vec4(color.rgb * light, color.a);
+}
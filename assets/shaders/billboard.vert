@@ -0,0 +1,33 @@
+layout (location = 0) in vec3 aPos;
+layout (location = 2) in vec2 aTexCoords;
+
+uniform vec3 center;
+uniform vec2 size;
+
+// Turn only about world up instead of facing the camera head on
+uniform bool cylindrical;
+
+// UV offset and scale of the current sprite sheet frame
+uniform vec4 frame_rect;
+
+out vec2 TexCoords;
+out vec3 FragPos;
+out vec3 Normal;
+
+void main()
+{
+  // The view matrix's rows are the camera's axes in world space
+  vec3 right = vec3(view[0][0], view[1][0], view[2][0]);
+  vec3 up = vec3(view[0][1], view[1][1], view[2][1]);
+  if (cylindrical) {
+    right = normalize(vec3(right.x, 0., right.z));
+    up = vec3(0., 1., 0.);
+  }
+
+  // Corners come from the x and z of a unit plane
+  FragPos = center + right * aPos.x * size.x + up * aPos.z * size.y;
+  Normal = cross(right, up);
+  TexCoords = frame_rect.xy + aTexCoords * frame_rect.zw;
+
+  gl_Position = projection * view * vec4(FragPos, 1.0);
+}
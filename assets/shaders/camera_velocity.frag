@@ -0,0 +1,23 @@
+out vec4 Velocity;
+
+in vec2 TexCoords;
+
+uniform sampler2D depthTexture;
+uniform mat4 inverse_view_projection;
+
+void main()
+{
+  // Reconstruct the world position of this pixel and project it with last
+  // frame's camera. Nothing here moves on its own, so ba is left at zero.
+  float depth = texture(depthTexture, TexCoords).r;
+  vec4 world = inverse_view_projection * vec4(vec3(TexCoords, depth) * 2.0 - 1.0, 1.0);
+  world /= world.w;
+
+  // Measured between unjittered positions, like velocity.frag, so that
+  // jitter alone doesn't count as motion
+  vec4 current = view_projection * world;
+  vec4 prev = prev_view_projection * world;
+  vec2 currentUv = current.xy / current.w * 0.5 + 0.5;
+  vec2 prevUv = prev.xy / prev.w * 0.5 + 0.5;
+  Velocity = vec4(currentUv - prevUv, 0.0, 0.0);
+}
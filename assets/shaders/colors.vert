@@ -16,6 +16,9 @@ out VS_OUT {
 } vs_out;
 #endif
 
+// Lets velocity.vert reproduce these positions exactly
+invariant gl_Position;
+
 void main()
 {
   #ifdef WASM
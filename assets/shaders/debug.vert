@@ -0,0 +1,10 @@
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec4 aColor;
+
+out vec4 Color;
+
+void main()
+{
+  Color = aColor;
+  gl_Position = projection * view * vec4(aPos, 1.0);
+}
@@ -0,0 +1,30 @@
+in vec2 TexCoords;
+
+// 0 = wireframe, 1 = UV checker, 2 = material ID
+uniform int mode;
+uniform int material_id;
+uniform vec4 wire_color;
+
+out vec4 FragColor;
+
+vec3 hue(float h) {
+  vec3 rgb = abs(mod(h * 6. + vec3(0., 4., 2.), 6.) - 3.) - 1.;
+  return clamp(rgb, 0., 1.);
+}
+
+void main()
+{
+  if (mode == 0) {
+    FragColor = wire_color;
+  } else if (mode == 1) {
+    // 8x8 checkerboard tinted by UV so that orientation is visible
+    vec2 cell = floor(fract(TexCoords) * 8.);
+    float check = mod(cell.x + cell.y, 2.);
+    vec3 tint = vec3(fract(TexCoords), 0.5);
+    FragColor = vec4(mix(tint * 0.4, tint, check), 1.0);
+  } else {
+    // Spread consecutive IDs around the color wheel with the golden ratio
+    vec3 color = material_id < 0 ? vec3(0.5) : hue(fract(float(material_id) * 0.618034));
+    FragColor = vec4(color, 1.0);
+  }
+}
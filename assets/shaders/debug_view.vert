@@ -0,0 +1,29 @@
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 2) in vec2 aTexCoords;
+layout (location = 3) in ivec4 aJoints;
+layout (location = 4) in vec4 aWeights;
+
+// Must match MAX_JOINTS in animation.rs
+#define MAX_JOINTS 64
+
+uniform mat4 model;
+uniform bool skinned;
+uniform mat4 joint_matrices[MAX_JOINTS];
+
+out vec2 TexCoords;
+
+void main()
+{
+  mat4 world = model;
+  if (skinned) {
+    world = model * (
+      aWeights.x * joint_matrices[aJoints.x] +
+      aWeights.y * joint_matrices[aJoints.y] +
+      aWeights.z * joint_matrices[aJoints.z] +
+      aWeights.w * joint_matrices[aJoints.w]);
+  }
+
+  TexCoords = aTexCoords;
+  gl_Position = projection * view * world * vec4(aPos, 1.0);
+}
@@ -1,13 +1,42 @@
 out vec4 FragColor;
 
-float near = 0.1; 
-float far  = 100.0; 
+in vec2 TexCoords;
+
+uniform sampler2D depthTexture;
+uniform float near;
+uniform float far;
+uniform bool false_color;
+
+// Polynomial fit of the Turbo colormap
+vec3 turbo(float t) {
+  const vec4 kRedVec4 = vec4(0.13572138, 4.61539260, -42.66032258, 132.13108234);
+  const vec4 kGreenVec4 = vec4(0.09140261, 2.19418839, 4.84296658, -14.18503333);
+  const vec4 kBlueVec4 = vec4(0.10667330, 12.64194608, -60.58204836, 110.36276771);
+  const vec2 kRedVec2 = vec2(-152.94239396, 59.28637943);
+  const vec2 kGreenVec2 = vec2(4.27729857, 2.82956604);
+  const vec2 kBlueVec2 = vec2(-89.90310912, 27.34824973);
+
+  t = clamp(t, 0., 1.);
+  vec4 v4 = vec4(1.0, t, t * t, t * t * t);
+  vec2 v2 = v4.zw * v4.z;
+  return vec3(
+    dot(v4, kRedVec4) + dot(v2, kRedVec2),
+    dot(v4, kGreenVec4) + dot(v2, kGreenVec2),
+    dot(v4, kBlueVec4) + dot(v2, kBlueVec2)
+  );
+}
 
 void main()
 {
-  float ndc = gl_FragCoord.z * 2.0 - 1.0; 
-  float linearDepth = (2.0 * near * far) / (far + near - ndc * (far - near));	
+  float z = texture(depthTexture, TexCoords).r;
+  float ndc = z * 2.0 - 1.0;
+  float linearDepth = (2.0 * near * far) / (far + near - ndc * (far - near));
   float depth = linearDepth / far;
 
-  FragColor = vec4(vec3(depth), 1.0);
-}
\ No newline at end of file
+  if (false_color) {
+    // Square root spreads the ramp over the near range where most detail is
+    FragColor = vec4(turbo(sqrt(depth)), 1.0);
+  } else {
+    FragColor = vec4(vec3(depth), 1.0);
+  }
+}
@@ -0,0 +1,48 @@
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D screenTexture;
+uniform sampler2D depthTexture;
+
+// Sun in screen UV and its color, faded as it leaves the screen
+uniform vec2 sun_position;
+uniform vec3 sun_color;
+
+uniform float density;
+uniform float decay;
+uniform float weight;
+uniform float exposure;
+uniform int samples;
+
+// Must match MAX_SAMPLES in volumetrics.rs
+#define MAX_SAMPLES 128
+
+// Only the sky lets light through, everything else occludes it
+vec3 light_at(vec2 uv) {
+  float sky = step(0.99999, texture(depthTexture, uv).r);
+  return texture(screenTexture, uv).rgb * sky;
+}
+
+void main()
+{
+  vec3 color = texture(screenTexture, TexCoords).rgb;
+
+  // Walk from the pixel toward the sun, summing light with exponential decay
+  // (Mitchell, "Volumetric Light Scattering as a Post-Process", GPU Gems 3)
+  vec2 delta = (TexCoords - sun_position) * density / float(samples);
+  vec2 uv = TexCoords;
+  float illumination_decay = 1.0;
+  vec3 scattered = vec3(0.0);
+  for (int i = 0; i < MAX_SAMPLES; i++) {
+    if (i >= samples) {
+      break;
+    }
+
+    uv -= delta;
+    scattered += light_at(uv) * illumination_decay * weight;
+    illumination_decay *= decay;
+  }
+
+  FragColor = vec4(color + scattered * sun_color * exposure, 1.0);
+}
@@ -0,0 +1,72 @@
+// Phong shading of a surface by all the scene's lights
+
+#include "lights.glsl"
+
+// What the lights shine on, in world space
+struct Surface {
+  vec3 position;
+  vec3 normal;
+  vec4 diffuse;
+  vec4 specular;
+  float shininess;
+};
+
+vec4 compute_light(Surface surface, vec3 lightVec, vec3 light_ambient, vec3 light_diffuse, vec3 light_specular) {
+  // Ambient
+  vec4 ambient = vec4(light_ambient, 1.0) * surface.diffuse;
+
+  // Diffuse
+  vec3 norm = normalize(surface.normal);
+  vec3 lightDir = normalize(lightVec);
+  float diff = max(dot(norm, lightDir), 0.);
+  vec4 diffuse = vec4(light_diffuse, 1.0) * diff * surface.diffuse;
+
+  // Specular
+  vec3 viewDir = normalize(view_pos - surface.position);
+  vec3 reflectDir = reflect(-lightDir, norm);
+  float spec = pow(max(dot(viewDir, reflectDir), 0.0), surface.shininess);
+  vec4 specular = vec4(light_specular, 1.0) * spec * surface.specular;
+
+  return ambient + diffuse + specular;
+}
+
+vec4 compute_dir_light(Surface surface, DirLight light) {
+  return compute_light(surface, -light.direction, light.ambient, light.diffuse, light.specular);
+}
+
+vec4 compute_point_light(Surface surface, PointLight light) {
+  vec3 lightVec = light.position - surface.position;
+  float d = length(lightVec);
+  return compute_light(surface, lightVec, light.ambient, light.diffuse, light.specular)
+    * attenuation(d, light.constant, light.linear, light.quadratic);
+}
+
+vec4 compute_spot_light(Surface surface, SpotLight light) {
+  vec3 lightVec = light.position - surface.position;
+  float intensity = spot_intensity(light, normalize(lightVec));
+  if (intensity <= 0.) {
+    return vec4(0.);
+  }
+
+  float d = length(lightVec);
+  return compute_light(surface, lightVec, light.ambient, light.diffuse, light.specular)
+    * attenuation(d, light.constant, light.linear, light.quadratic) * intensity;
+}
+
+vec4 compute_lights(Surface surface) {
+  vec4 result = vec4(0.);
+
+  for (int i = 0; i < dir_lights_len; ++i) {
+    result += compute_dir_light(surface, dir_lights[i]);
+  }
+
+  for (int i = 0; i < point_lights_len; ++i) {
+    result += compute_point_light(surface, point_lights[i]);
+  }
+
+  for (int i = 0; i < spot_lights_len; ++i) {
+    result += compute_spot_light(surface, spot_lights[i]);
+  }
+
+  return result;
+}
@@ -0,0 +1,19 @@
+// Light uniforms the scene binds, and how each light type falls off
+
+uniform DirLight dir_lights[4];
+uniform int dir_lights_len;
+uniform SpotLight spot_lights[16];
+uniform int spot_lights_len;
+uniform PointLight point_lights[16];
+uniform int point_lights_len;
+
+float attenuation(float d, float constant, float linear, float quadratic) {
+  return 1.0 / (constant + linear * d + quadratic * d * d);
+}
+
+// 1 inside the inner cone, fading to 0 at the outer cone
+float spot_intensity(SpotLight light, vec3 lightDir) {
+  float theta = dot(lightDir, normalize(-light.direction));
+  float epsilon = light.inner_cut_off - light.outer_cut_off;
+  return clamp((theta - light.outer_cut_off) / epsilon, 0., 1.);
+}
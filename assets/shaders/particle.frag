@@ -0,0 +1,24 @@
+in vec2 TexCoords;
+in vec4 Color;
+
+uniform bool has_texture;
+uniform sampler2D particle_tex;
+
+out vec4 FragColor;
+
+void main()
+{
+  vec4 texel;
+  if (has_texture) {
+    texel = texture(particle_tex, TexCoords);
+  } else {
+    // Soft round sprite when no texture is given
+    float d = length(TexCoords * 2. - 1.);
+    texel = vec4(1., 1., 1., 1. - smoothstep(0.5, 1., d));
+  }
+
+  FragColor = Color * texel;
+  if (FragColor.a < 0.01) {
+    discard;
+  }
+}
@@ -0,0 +1,62 @@
+layout (location = 0) in vec3 center;
+layout (location = 1) in vec3 velocity;
+layout (location = 2) in float age;
+layout (location = 3) in float lifetime;
+
+// Must match MAX_CURVE_KEYS in particles.rs
+#define MAX_CURVE_KEYS 8
+
+uniform float color_times[MAX_CURVE_KEYS];
+uniform vec4 color_values[MAX_CURVE_KEYS];
+uniform int color_len;
+
+uniform float size_times[MAX_CURVE_KEYS];
+uniform float size_values[MAX_CURVE_KEYS];
+uniform int size_len;
+
+out vec2 TexCoords;
+out vec4 Color;
+
+vec4 sample_color(float t) {
+  for (int i = 1; i < color_len; ++i) {
+    if (t <= color_times[i]) {
+      float span = max(color_times[i] - color_times[i - 1], 1e-5);
+      return mix(color_values[i - 1], color_values[i], clamp((t - color_times[i - 1]) / span, 0., 1.));
+    }
+  }
+  return color_values[color_len - 1];
+}
+
+float sample_size(float t) {
+  for (int i = 1; i < size_len; ++i) {
+    if (t <= size_times[i]) {
+      float span = max(size_times[i] - size_times[i - 1], 1e-5);
+      return mix(size_values[i - 1], size_values[i], clamp((t - size_times[i - 1]) / span, 0., 1.));
+    }
+  }
+  return size_values[size_len - 1];
+}
+
+void main()
+{
+  // Dead particles are moved outside the clip volume
+  if (age >= lifetime) {
+    gl_Position = vec4(2., 2., 2., 1.);
+    return;
+  }
+
+  float t = age / lifetime;
+  Color = sample_color(t);
+
+  // Generate quad corners from the vertex ID like text.vert
+  vec2 corner = vec2(float(gl_VertexID % 2), float(gl_VertexID / 2));
+  TexCoords = corner;
+
+  // The rows of the view matrix are the camera's right and up vectors in world space
+  vec3 right = vec3(view[0][0], view[1][0], view[2][0]);
+  vec3 up = vec3(view[0][1], view[1][1], view[2][1]);
+  vec2 offset = (corner * 2. - 1.) * sample_size(t) * 0.5;
+  vec3 position = center + right * offset.x + up * offset.y;
+
+  gl_Position = projection * view * vec4(position, 1.0);
+}
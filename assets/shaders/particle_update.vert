@@ -0,0 +1,57 @@
+layout (location = 0) in vec3 in_position;
+layout (location = 1) in vec3 in_velocity;
+layout (location = 2) in float in_age;
+layout (location = 3) in float in_lifetime;
+
+out vec3 out_position;
+out vec3 out_velocity;
+out float out_age;
+out float out_lifetime;
+
+uniform float dt;
+uniform float time;
+uniform vec3 origin;
+uniform vec3 direction;
+uniform float spread;
+uniform vec3 gravity;
+uniform vec2 speed_range;
+uniform vec2 lifetime_range;
+
+// Dead particles in [spawn_start, spawn_start + spawn_count) (mod max_particles) are respawned
+uniform int spawn_start;
+uniform int spawn_count;
+uniform int max_particles;
+
+float hash(float n) {
+  return fract(sin(n) * 43758.5453123);
+}
+
+vec3 cone_direction(vec3 axis, float r1, float r2) {
+  float cos_theta = mix(1., cos(spread), r1);
+  float sin_theta = sqrt(max(1. - cos_theta * cos_theta, 0.));
+  float phi = r2 * 6.2831853;
+  vec3 up = abs(axis.y) < 0.999 ? vec3(0., 1., 0.) : vec3(1., 0., 0.);
+  vec3 tangent = normalize(cross(up, axis));
+  vec3 bitangent = cross(axis, tangent);
+  return normalize(tangent * cos(phi) * sin_theta + bitangent * sin(phi) * sin_theta + axis * cos_theta);
+}
+
+void main()
+{
+  int slot = (gl_VertexID - spawn_start + max_particles) % max_particles;
+  float age = in_age + dt;
+
+  if (age >= in_lifetime && slot < spawn_count) {
+    float seed = float(gl_VertexID) * 12.9898 + time * 78.233;
+    vec3 dir = cone_direction(normalize(direction), hash(seed), hash(seed + 1.));
+    out_position = origin;
+    out_velocity = dir * mix(speed_range.x, speed_range.y, hash(seed + 2.));
+    out_age = 0.;
+    out_lifetime = mix(lifetime_range.x, lifetime_range.y, hash(seed + 3.));
+  } else {
+    out_velocity = in_velocity + gravity * dt;
+    out_position = in_position + out_velocity * dt;
+    out_age = age;
+    out_lifetime = in_lifetime;
+  }
+}
@@ -0,0 +1,44 @@
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D screenTexture;
+uniform vec2 texel_size;
+
+// Must match MAX_KERNEL_LEN in post_process.rs
+#define MAX_KERNEL_LEN 64
+uniform float kernel[MAX_KERNEL_LEN];
+uniform int kernel_size;
+
+// Separable kernels are 1D and run once along each direction
+uniform bool separable;
+uniform vec2 direction;
+
+// Signed kernels (edges, emboss) can be shown as magnitude or offset to gray
+uniform bool absolute;
+uniform float bias;
+
+void main() {
+  int radius = kernel_size / 2;
+  vec3 col = vec3(0.0);
+
+  if (separable) {
+    for (int i = 0; i < kernel_size; i++) {
+      vec2 offset = direction * float(i - radius) * texel_size;
+      col += vec3(texture(screenTexture, TexCoords + offset)) * kernel[i];
+    }
+  } else {
+    for (int y = 0; y < kernel_size; y++) {
+      for (int x = 0; x < kernel_size; x++) {
+        // Kernel rows go top to bottom, texture coordinates go bottom to top
+        vec2 offset = vec2(float(x - radius), float(radius - y)) * texel_size;
+        col += vec3(texture(screenTexture, TexCoords + offset)) * kernel[y * kernel_size + x];
+      }
+    }
+  }
+
+  if (absolute) {
+    col = abs(col);
+  }
+  FragColor = vec4(col + bias, 1.0);
+}
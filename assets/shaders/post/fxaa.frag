@@ -0,0 +1,165 @@
+// Fast approximate anti-aliasing, following the structure of FXAA 3.11 quality:
+// find edges by local luma contrast, search along each edge for its ends, then
+// resample across the edge in proportion to where the pixel sits along it.
+
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D screenTexture;
+uniform vec2 texel_size;
+
+uniform float edge_threshold;
+uniform float edge_threshold_min;
+uniform float subpixel;
+uniform int search_steps;
+
+// Show detected edges instead of the anti-aliased image: horizontal edges are
+// yellow, vertical edges blue, brighter where more blending happens
+uniform bool show_edges;
+
+// Must be at least the largest search_steps in post_process.rs
+#define MAX_SEARCH_STEPS 12
+
+float luma(vec3 color) {
+  // Approximate perceptual brightness
+  return sqrt(dot(color, vec3(0.299, 0.587, 0.114)));
+}
+
+float lumaAt(vec2 uv) {
+  return luma(texture(screenTexture, uv).rgb);
+}
+
+float lumaOffset(float x, float y) {
+  return lumaAt(TexCoords + vec2(x, y) * texel_size);
+}
+
+// Search steps grow further from the pixel to reach long edges quickly
+float searchStep(int i) {
+  if (i < 5) return 1.0;
+  if (i == 5) return 1.5;
+  if (i < 10) return 2.0;
+  if (i == 10) return 4.0;
+  return 8.0;
+}
+
+void main() {
+  vec3 color = texture(screenTexture, TexCoords).rgb;
+  float lumaCenter = luma(color);
+
+  float lumaDown = lumaOffset(0., -1.);
+  float lumaUp = lumaOffset(0., 1.);
+  float lumaLeft = lumaOffset(-1., 0.);
+  float lumaRight = lumaOffset(1., 0.);
+
+  float lumaMin = min(lumaCenter, min(min(lumaDown, lumaUp), min(lumaLeft, lumaRight)));
+  float lumaMax = max(lumaCenter, max(max(lumaDown, lumaUp), max(lumaLeft, lumaRight)));
+  float range = lumaMax - lumaMin;
+
+  // Not enough contrast to be an edge
+  if (range < max(edge_threshold_min, lumaMax * edge_threshold)) {
+    FragColor = show_edges ? vec4(vec3(lumaCenter * 0.3), 1.0) : vec4(color, 1.0);
+    return;
+  }
+
+  float lumaDownLeft = lumaOffset(-1., -1.);
+  float lumaUpRight = lumaOffset(1., 1.);
+  float lumaUpLeft = lumaOffset(-1., 1.);
+  float lumaDownRight = lumaOffset(1., -1.);
+
+  float lumaDownUp = lumaDown + lumaUp;
+  float lumaLeftRight = lumaLeft + lumaRight;
+  float lumaLeftCorners = lumaDownLeft + lumaUpLeft;
+  float lumaDownCorners = lumaDownLeft + lumaDownRight;
+  float lumaRightCorners = lumaDownRight + lumaUpRight;
+  float lumaUpCorners = lumaUpRight + lumaUpLeft;
+
+  // Decide whether the edge runs horizontally or vertically
+  float edgeHorizontal = abs(-2.0 * lumaLeft + lumaLeftCorners)
+    + abs(-2.0 * lumaCenter + lumaDownUp) * 2.0
+    + abs(-2.0 * lumaRight + lumaRightCorners);
+  float edgeVertical = abs(-2.0 * lumaUp + lumaUpCorners)
+    + abs(-2.0 * lumaCenter + lumaLeftRight) * 2.0
+    + abs(-2.0 * lumaDown + lumaDownCorners);
+  bool isHorizontal = edgeHorizontal >= edgeVertical;
+
+  // Pick the side of the pixel with the steeper gradient
+  float luma1 = isHorizontal ? lumaDown : lumaLeft;
+  float luma2 = isHorizontal ? lumaUp : lumaRight;
+  float gradient1 = luma1 - lumaCenter;
+  float gradient2 = luma2 - lumaCenter;
+  bool is1Steepest = abs(gradient1) >= abs(gradient2);
+  float gradientScaled = 0.25 * max(abs(gradient1), abs(gradient2));
+
+  float stepLength = isHorizontal ? texel_size.y : texel_size.x;
+  float lumaLocalAverage;
+  if (is1Steepest) {
+    stepLength = -stepLength;
+    lumaLocalAverage = 0.5 * (luma1 + lumaCenter);
+  } else {
+    lumaLocalAverage = 0.5 * (luma2 + lumaCenter);
+  }
+
+  // Start halfway between this pixel and its neighbor across the edge
+  vec2 edgeUv = TexCoords;
+  if (isHorizontal) {
+    edgeUv.y += stepLength * 0.5;
+  } else {
+    edgeUv.x += stepLength * 0.5;
+  }
+
+  // Walk both ways along the edge until the luma no longer matches it
+  vec2 offset = isHorizontal ? vec2(texel_size.x, 0.0) : vec2(0.0, texel_size.y);
+  vec2 uv1 = edgeUv - offset;
+  vec2 uv2 = edgeUv + offset;
+  float lumaEnd1 = lumaAt(uv1) - lumaLocalAverage;
+  float lumaEnd2 = lumaAt(uv2) - lumaLocalAverage;
+  bool reached1 = abs(lumaEnd1) >= gradientScaled;
+  bool reached2 = abs(lumaEnd2) >= gradientScaled;
+  if (!reached1) uv1 -= offset;
+  if (!reached2) uv2 += offset;
+
+  for (int i = 2; i < MAX_SEARCH_STEPS; i++) {
+    if (i >= search_steps || (reached1 && reached2)) {
+      break;
+    }
+    if (!reached1) lumaEnd1 = lumaAt(uv1) - lumaLocalAverage;
+    if (!reached2) lumaEnd2 = lumaAt(uv2) - lumaLocalAverage;
+    reached1 = abs(lumaEnd1) >= gradientScaled;
+    reached2 = abs(lumaEnd2) >= gradientScaled;
+    if (!reached1) uv1 -= offset * searchStep(i);
+    if (!reached2) uv2 += offset * searchStep(i);
+  }
+
+  float distance1 = isHorizontal ? (TexCoords.x - uv1.x) : (TexCoords.y - uv1.y);
+  float distance2 = isHorizontal ? (uv2.x - TexCoords.x) : (uv2.y - TexCoords.y);
+  bool isDirection1 = distance1 < distance2;
+  float distanceFinal = min(distance1, distance2);
+  float edgeLength = distance1 + distance2;
+
+  // Only blend if the nearest edge end agrees with which side this pixel is on
+  float pixelOffset = -distanceFinal / edgeLength + 0.5;
+  bool isLumaCenterSmaller = lumaCenter < lumaLocalAverage;
+  bool correctVariation = ((isDirection1 ? lumaEnd1 : lumaEnd2) < 0.0) != isLumaCenterSmaller;
+  float finalOffset = correctVariation ? pixelOffset : 0.0;
+
+  // Sub-pixel aliasing: blend more where the pixel differs from its 3x3 average
+  float lumaAverage = (1.0 / 12.0) * (2.0 * (lumaDownUp + lumaLeftRight) + lumaLeftCorners + lumaRightCorners);
+  float subPixelOffset1 = clamp(abs(lumaAverage - lumaCenter) / range, 0.0, 1.0);
+  float subPixelOffset2 = (-2.0 * subPixelOffset1 + 3.0) * subPixelOffset1 * subPixelOffset1;
+  finalOffset = max(finalOffset, subPixelOffset2 * subPixelOffset2 * subpixel);
+
+  if (show_edges) {
+    vec3 edgeColor = isHorizontal ? vec3(1.0, 0.8, 0.0) : vec3(0.0, 0.6, 1.0);
+    FragColor = vec4(edgeColor * (0.5 + finalOffset), 1.0);
+    return;
+  }
+
+  vec2 finalUv = TexCoords;
+  if (isHorizontal) {
+    finalUv.y += finalOffset * stepLength;
+  } else {
+    finalUv.x += finalOffset * stepLength;
+  }
+  FragColor = vec4(texture(screenTexture, finalUv).rgb, 1.0);
+}
@@ -0,0 +1,20 @@
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D screenTexture;
+
+// Fraction of the screen width, from the left, that uses perceptual weights
+// rather than a plain average
+uniform float split;
+
+void main() {
+  vec3 pixel = vec3(texture(screenTexture, TexCoords));
+  float average;
+  if (TexCoords.x < split) {
+    average = 0.2126 * pixel.r + 0.7152 * pixel.g + 0.0722 * pixel.b;
+  } else {
+    average = 0.33 * pixel.r + 0.33 * pixel.g + 0.33 * pixel.b;
+  }
+  FragColor = vec4(average, average, average, 1.0);
+}
@@ -0,0 +1,10 @@
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D screenTexture;
+
+void main() {
+  vec3 pixel = vec3(texture(screenTexture, TexCoords));
+  FragColor = vec4(1.0 - pixel, 1.0);
+}
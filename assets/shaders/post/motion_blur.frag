@@ -0,0 +1,27 @@
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D screenTexture;
+uniform sampler2D velocityTexture;
+
+// Fraction of a frame's motion to smear over
+uniform float strength;
+uniform int samples;
+
+// Only blur objects moving by themselves, not camera motion
+uniform bool per_object;
+
+void main()
+{
+  vec4 velocities = texture(velocityTexture, TexCoords);
+  vec2 velocity = (per_object ? velocities.ba : velocities.rg) * strength;
+
+  // Average samples along the motion, centered on the pixel
+  vec3 col = vec3(0.0);
+  for (int i = 0; i < samples; i++) {
+    float t = float(i) / float(max(samples - 1, 1)) - 0.5;
+    col += texture(screenTexture, TexCoords - velocity * t).rgb;
+  }
+  FragColor = vec4(col / float(max(samples, 1)), 1.0);
+}
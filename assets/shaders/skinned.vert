@@ -0,0 +1,35 @@
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 2) in vec2 aTexCoords;
+layout (location = 3) in ivec4 aJoints;
+layout (location = 4) in vec4 aWeights;
+
+// Must match MAX_JOINTS in animation.rs
+#define MAX_JOINTS 64
+
+uniform mat4 model;
+uniform mat4 joint_matrices[MAX_JOINTS];
+
+out vec3 Normal;
+out vec3 FragPos;
+out vec2 TexCoords;
+
+// Lets velocity.vert reproduce these positions exactly
+invariant gl_Position;
+
+void main()
+{
+  // Linear blend skinning: weighted sum of each influencing joint's transform
+  mat4 skin =
+    aWeights.x * joint_matrices[aJoints.x] +
+    aWeights.y * joint_matrices[aJoints.y] +
+    aWeights.z * joint_matrices[aJoints.z] +
+    aWeights.w * joint_matrices[aJoints.w];
+  mat4 skinned_model = model * skin;
+
+  Normal = mat3(transpose(inverse(skinned_model))) * aNormal;
+  FragPos = vec3(skinned_model * vec4(aPos, 1.0));
+  TexCoords = aTexCoords;
+
+  gl_Position = projection * view * vec4(FragPos, 1.0);
+}
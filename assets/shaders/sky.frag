@@ -0,0 +1,71 @@
+in vec3 TexCoords;
+
+// Unit vector toward the sun
+uniform vec3 sun_direction;
+uniform float turbidity;
+
+out vec4 FragColor;
+
+#define PI 3.14159265
+
+// Angular radius of the sun's disk, exaggerated
+#define SUN_RADIUS 0.02
+
+// Perez et al.'s sky luminance distribution for a view at zenith angle theta,
+// gamma away from the sun
+float perez(float theta, float gamma, float A, float B, float C, float D, float E) {
+  return (1. + A * exp(B / max(cos(theta), 0.01))) * (1. + C * exp(D * gamma) + E * cos(gamma) * cos(gamma));
+}
+
+// Preetham, Shirley and Smits, "A Practical Analytic Model for Daylight"
+vec3 preetham(vec3 view_dir, vec3 sun_dir) {
+  float T = turbidity;
+
+  // The model only covers the sun above the horizon
+  float theta_s = acos(clamp(sun_dir.y, 0.01, 1.));
+  float theta = acos(clamp(view_dir.y, 0.001, 1.));
+  float gamma = acos(clamp(dot(view_dir, sun_dir), -1., 1.));
+
+  // Zenith luminance in kcd/m^2 and chromaticity
+  float chi = (4. / 9. - T / 120.) * (PI - 2. * theta_s);
+  float Yz = (4.0453 * T - 4.9710) * tan(chi) - 0.2155 * T + 2.4192;
+  vec3 th = vec3(theta_s * theta_s * theta_s, theta_s * theta_s, theta_s);
+  float xz = T * T * dot(vec3(0.00166, -0.00375, 0.00209), th)
+    + T * (dot(vec3(-0.02903, 0.06377, -0.03202), th) + 0.00394)
+    + dot(vec3(0.11693, -0.21196, 0.06052), th) + 0.25886;
+  float yz = T * T * dot(vec3(0.00275, -0.00610, 0.00317), th)
+    + T * (dot(vec3(-0.04214, 0.08970, -0.04153), th) + 0.00516)
+    + dot(vec3(0.15346, -0.26756, 0.06670), th) + 0.26688;
+
+  float Y = Yz * perez(theta, gamma, 0.1787 * T - 1.4630, -0.3554 * T + 0.4275, -0.0227 * T + 5.3251, 0.1206 * T - 2.5771, -0.0670 * T + 0.3703)
+    / perez(0., theta_s, 0.1787 * T - 1.4630, -0.3554 * T + 0.4275, -0.0227 * T + 5.3251, 0.1206 * T - 2.5771, -0.0670 * T + 0.3703);
+  float x = xz * perez(theta, gamma, -0.0193 * T - 0.2592, -0.0665 * T + 0.0008, -0.0004 * T + 0.2125, -0.0641 * T - 0.8989, -0.0033 * T + 0.0452)
+    / perez(0., theta_s, -0.0193 * T - 0.2592, -0.0665 * T + 0.0008, -0.0004 * T + 0.2125, -0.0641 * T - 0.8989, -0.0033 * T + 0.0452);
+  float y = yz * perez(theta, gamma, -0.0167 * T - 0.2608, -0.0950 * T + 0.0092, -0.0079 * T + 0.2102, -0.0441 * T - 1.6537, -0.0109 * T + 0.0529)
+    / perez(0., theta_s, -0.0167 * T - 0.2608, -0.0950 * T + 0.0092, -0.0079 * T + 0.2102, -0.0441 * T - 1.6537, -0.0109 * T + 0.0529);
+
+  // xyY to XYZ to linear sRGB
+  vec3 XYZ = vec3(x * Y / y, Y, (1. - x - y) * Y / y);
+  mat3 xyz_to_rgb = mat3(
+    3.2406, -0.9689, 0.0557,
+    -1.5372, 1.8758, -0.2040,
+    -0.4986, 0.0415, 1.0570
+  );
+  return max(xyz_to_rgb * XYZ, vec3(0.));
+}
+
+void main()
+{
+  vec3 view_dir = normalize(TexCoords);
+  vec3 sun_dir = normalize(sun_direction);
+
+  // Fade to a night sky as the sun sets, and to dark ground below the horizon
+  float day = smoothstep(-0.1, 0.05, sun_dir.y);
+  vec3 sky = preetham(view_dir, sun_dir) * 0.06;
+  sky += vec3(40.) * (1. - smoothstep(SUN_RADIUS * 0.8, SUN_RADIUS, acos(clamp(dot(view_dir, sun_dir), -1., 1.))));
+  sky = mix(vec3(0.005, 0.008, 0.02), sky * day, day);
+  sky = mix(sky, sky * 0.3, 1. - smoothstep(-0.1, 0., view_dir.y));
+
+  // Exposure tone mapping, as the model's luminance is unbounded
+  FragColor = vec4(1. - exp(-sky), 1.);
+}
@@ -0,0 +1,71 @@
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D screenTexture;
+uniform sampler2D depthTexture;
+uniform mat4 inverse_projection;
+uniform mat4 inverse_view;
+
+#include "lights.glsl"
+
+uniform float fog_density;
+uniform int steps;
+uniform float max_distance;
+
+// Must match MAX_STEPS in volumetrics.rs
+#define MAX_STEPS 128
+
+vec3 world_position(vec2 uv) {
+  float depth = texture(depthTexture, uv).r;
+  vec4 pos = inverse_projection * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+  return (inverse_view * vec4(pos.xyz / pos.w, 1.0)).xyz;
+}
+
+// Light reaching pos from inside a spot light's cone, unshadowed
+vec3 spot_radiance(SpotLight light, vec3 pos) {
+  vec3 lightVec = light.position - pos;
+  float d = length(lightVec);
+  return light.diffuse * spot_intensity(light, lightVec / d)
+    * attenuation(d, light.constant, light.linear, light.quadratic);
+}
+
+// Per pixel offset that turns banding between steps into noise, which TAA
+// then smooths out (Jimenez, interleaved gradient noise)
+float dither() {
+  return fract(52.9829189 * fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))));
+}
+
+void main()
+{
+  vec3 color = texture(screenTexture, TexCoords).rgb;
+
+  // March from the eye to the visible surface, or max_distance into the sky
+  vec3 eye = view_pos;
+  vec3 ray = world_position(TexCoords) - eye;
+  float ray_length = min(length(ray), max_distance);
+  ray = normalize(ray);
+
+  float step_size = ray_length / float(steps);
+  float transmittance = 1.0;
+  vec3 scattered = vec3(0.0);
+  for (int i = 0; i < MAX_STEPS; i++) {
+    if (i >= steps) {
+      break;
+    }
+
+    vec3 pos = eye + ray * (float(i) + dither()) * step_size;
+    vec3 radiance = vec3(0.0);
+    for (int j = 0; j < spot_lights_len; ++j) {
+      radiance += spot_radiance(spot_lights[j], pos);
+    }
+
+    // Beer-Lambert extinction, with light scattered equally in all directions
+    float extinction = exp(-fog_density * step_size);
+    scattered += radiance * transmittance * (1.0 - extinction);
+    transmittance *= extinction;
+  }
+
+  // The fog is thin enough outside the cones not to dim what's behind it
+  FragColor = vec4(color + scattered, 1.0);
+}
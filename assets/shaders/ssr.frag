@@ -0,0 +1,94 @@
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D screenTexture;
+uniform sampler2D depthTexture;
+uniform sampler2D normalTexture;
+uniform samplerCube skybox;
+uniform mat4 inverse_projection;
+
+uniform float max_distance;
+uniform int steps;
+uniform float thickness;
+
+// Must match MAX_STEPS in ssr.rs
+#define MAX_STEPS 128
+#define REFINE_STEPS 6
+
+vec3 view_position(vec2 uv) {
+  float depth = texture(depthTexture, uv).r;
+  vec4 pos = inverse_projection * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+  return pos.xyz / pos.w;
+}
+
+vec2 project(vec3 pos) {
+  vec4 clip = projection * vec4(pos, 1.0);
+  return clip.xy / clip.w * 0.5 + 0.5;
+}
+
+bool on_screen(vec2 uv) {
+  return all(greaterThanEqual(uv, vec2(0.0))) && all(lessThanEqual(uv, vec2(1.0)));
+}
+
+// How far the ray is behind the visible surface; view space z is negative
+float depth_behind(vec3 pos, vec2 uv) {
+  return view_position(uv).z - pos.z;
+}
+
+void main()
+{
+  vec3 color = texture(screenTexture, TexCoords).rgb;
+  vec4 normalReflectivity = texture(normalTexture, TexCoords);
+  float reflectivity = normalReflectivity.a;
+  if (reflectivity <= 0.0) {
+    FragColor = vec4(color, 1.0);
+    return;
+  }
+
+  vec3 origin = view_position(TexCoords);
+  vec3 normal = normalize(normalReflectivity.xyz);
+  vec3 ray = normalize(reflect(normalize(origin), normal));
+
+  // Skybox directions are in world space
+  vec3 reflection = texture(skybox, transpose(mat3(view)) * ray).rgb;
+
+  // March until the ray passes just behind the depth buffer
+  float stepSize = max_distance / float(steps);
+  vec3 pos = origin;
+  for (int i = 0; i < MAX_STEPS; i++) {
+    if (i >= steps) {
+      break;
+    }
+
+    pos += ray * stepSize;
+    vec2 uv = project(pos);
+    if (!on_screen(uv) || pos.z > 0.0) {
+      break;
+    }
+
+    float behind = depth_behind(pos, uv);
+    if (behind > 0.0 && behind < thickness) {
+      // Binary search between the last two steps for the crossing point
+      vec3 front = pos - ray * stepSize;
+      vec3 back = pos;
+      for (int j = 0; j < REFINE_STEPS; j++) {
+        vec3 mid = (front + back) * 0.5;
+        if (depth_behind(mid, project(mid)) > 0.0) {
+          back = mid;
+        } else {
+          front = mid;
+        }
+      }
+      uv = project(back);
+
+      // Fade hits near the screen edge into the skybox to hide the cut-off
+      vec2 edge = abs(uv * 2.0 - 1.0);
+      float fade = 1.0 - smoothstep(0.8, 1.0, max(edge.x, edge.y));
+      reflection = mix(reflection, texture(screenTexture, uv).rgb, fade);
+      break;
+    }
+  }
+
+  FragColor = vec4(mix(color, reflection, reflectivity), 1.0);
+}
@@ -0,0 +1,10 @@
+in vec3 ViewNormal;
+
+uniform Material material;
+
+out vec4 NormalReflectivity;
+
+void main()
+{
+  NormalReflectivity = vec4(normalize(ViewNormal), material.reflectivity);
+}
@@ -0,0 +1,37 @@
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 3) in ivec4 aJoints;
+layout (location = 4) in vec4 aWeights;
+
+// Must match MAX_JOINTS in animation.rs
+#define MAX_JOINTS 64
+
+uniform mat4 model;
+uniform bool skinned;
+uniform mat4 joint_matrices[MAX_JOINTS];
+
+out vec3 ViewNormal;
+
+// Positions must come out bit-identical to colors.vert and skinned.vert so that
+// the depth test against the scene's depth buffer passes
+invariant gl_Position;
+
+void main()
+{
+  mat4 world;
+  if (skinned) {
+    mat4 skin =
+      aWeights.x * joint_matrices[aJoints.x] +
+      aWeights.y * joint_matrices[aJoints.y] +
+      aWeights.z * joint_matrices[aJoints.z] +
+      aWeights.w * joint_matrices[aJoints.w];
+    world = model * skin;
+    vec3 worldPos = vec3(world * vec4(aPos, 1.0));
+    gl_Position = projection * view * vec4(worldPos, 1.0);
+  } else {
+    world = model;
+    gl_Position = projection * view * model * vec4(aPos, 1.0);
+  }
+
+  ViewNormal = mat3(view) * mat3(transpose(inverse(world))) * aNormal;
+}
@@ -0,0 +1,35 @@
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D screenTexture;
+uniform sampler2D historyTexture;
+uniform sampler2D velocityTexture;
+uniform vec2 texel_size;
+uniform float feedback;
+uniform bool history_valid;
+
+void main()
+{
+  vec3 current = texture(screenTexture, TexCoords).rgb;
+
+  // Clamp history to the range of colors around this pixel, which rejects
+  // history that no longer matches what's on screen (disocclusion, lighting)
+  vec3 neighborMin = current;
+  vec3 neighborMax = current;
+  for (int y = -1; y <= 1; y++) {
+    for (int x = -1; x <= 1; x++) {
+      vec3 neighbor = texture(screenTexture, TexCoords + vec2(x, y) * texel_size).rgb;
+      neighborMin = min(neighborMin, neighbor);
+      neighborMax = max(neighborMax, neighbor);
+    }
+  }
+
+  // Find where this pixel was last frame
+  vec2 prevUv = TexCoords - texture(velocityTexture, TexCoords).rg;
+  bool onScreen = all(greaterThanEqual(prevUv, vec2(0.0))) && all(lessThanEqual(prevUv, vec2(1.0)));
+
+  vec3 history = clamp(texture(historyTexture, prevUv).rgb, neighborMin, neighborMax);
+  float blend = history_valid && onScreen ? feedback : 0.0;
+  FragColor = vec4(mix(current, history, blend), 1.0);
+}
@@ -0,0 +1,62 @@
+in vec3 Normal;
+in vec3 FragPos;
+in vec2 TexCoords;
+
+// Channel i of the splat map weighs layer i
+uniform Material layers[4];
+uniform int layers_len;
+uniform sampler2D splat_map;
+
+// Repeats of the layer textures across the whole terrain
+uniform vec2 tiling;
+
+out vec4 FragColor;
+
+#include "lighting.glsl"
+
+// Layers blended at this fragment
+vec4 diffuse_tex;
+vec4 specular_tex;
+float shininess;
+
+// Samplers in arrays can only be indexed by constants on GLES, so each layer
+// is spelled out
+#define BLEND_LAYER(i, weight) \
+  if (layers_len > i) { \
+    diffuse_tex += weight * texture(layers[i].diffuse, uv); \
+    specular_tex += weight * texture(layers[i].specular, uv); \
+    shininess += weight * layers[i].shininess; \
+    total += weight; \
+  }
+
+void blend_layers() {
+  vec4 splat = texture(splat_map, TexCoords);
+  vec2 uv = TexCoords * tiling;
+  float total = 0.;
+  diffuse_tex = vec4(0.);
+  specular_tex = vec4(0.);
+  shininess = 0.;
+
+  BLEND_LAYER(0, splat.r)
+  BLEND_LAYER(1, splat.g)
+  BLEND_LAYER(2, splat.b)
+  BLEND_LAYER(3, splat.a)
+
+  // Fall back to the first layer where the splat map is empty
+  if (total < 0.001) {
+    diffuse_tex = texture(layers[0].diffuse, uv);
+    specular_tex = texture(layers[0].specular, uv);
+    shininess = layers[0].shininess;
+  } else {
+    diffuse_tex /= total;
+    specular_tex /= total;
+    shininess /= total;
+  }
+}
+
+void main()
+{
+  blend_layers();
+  vec4 result = compute_lights(Surface(FragPos, Normal, diffuse_tex, specular_tex, shininess));
+  FragColor = vec4(result.rgb, 1.);
+}
@@ -0,0 +1,15 @@
+in vec4 CurrentPos;
+in vec4 PrevPos;
+in vec4 ObjectPrevPos;
+
+out vec4 Velocity;
+
+vec2 to_uv(vec4 clip) {
+  return clip.xy / clip.w * 0.5 + 0.5;
+}
+
+void main()
+{
+  vec2 current = to_uv(CurrentPos);
+  Velocity = vec4(current - to_uv(PrevPos), current - to_uv(ObjectPrevPos));
+}
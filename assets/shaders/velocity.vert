@@ -0,0 +1,52 @@
+layout (location = 0) in vec3 aPos;
+layout (location = 3) in ivec4 aJoints;
+layout (location = 4) in vec4 aWeights;
+
+// Must match MAX_JOINTS in animation.rs
+#define MAX_JOINTS 64
+
+uniform mat4 model;
+uniform mat4 prev_model;
+uniform bool skinned;
+uniform mat4 joint_matrices[MAX_JOINTS];
+uniform mat4 prev_joint_matrices[MAX_JOINTS];
+
+out vec4 CurrentPos;
+out vec4 PrevPos;
+out vec4 ObjectPrevPos;
+
+// Positions must come out bit-identical to colors.vert and skinned.vert so that
+// the depth test against the scene's depth buffer passes
+invariant gl_Position;
+
+void main()
+{
+  vec4 worldPos;
+  vec4 prevWorldPos;
+  if (skinned) {
+    mat4 skin =
+      aWeights.x * joint_matrices[aJoints.x] +
+      aWeights.y * joint_matrices[aJoints.y] +
+      aWeights.z * joint_matrices[aJoints.z] +
+      aWeights.w * joint_matrices[aJoints.w];
+    mat4 prev_skin =
+      aWeights.x * prev_joint_matrices[aJoints.x] +
+      aWeights.y * prev_joint_matrices[aJoints.y] +
+      aWeights.z * prev_joint_matrices[aJoints.z] +
+      aWeights.w * prev_joint_matrices[aJoints.w];
+    mat4 skinned_model = model * skin;
+    worldPos = vec4(vec3(skinned_model * vec4(aPos, 1.0)), 1.0);
+    prevWorldPos = prev_model * prev_skin * vec4(aPos, 1.0);
+    gl_Position = projection * view * worldPos;
+  } else {
+    worldPos = model * vec4(aPos, 1.0);
+    prevWorldPos = prev_model * vec4(aPos, 1.0);
+    gl_Position = projection * view * model * vec4(aPos, 1.0);
+  }
+
+  CurrentPos = view_projection * worldPos;
+  PrevPos = prev_view_projection * prevWorldPos;
+
+  // Where the object was last frame as seen by this frame's camera
+  ObjectPrevPos = view_projection * prevWorldPos;
+}
@@ -0,0 +1,83 @@
+in vec3 FragPos;
+in vec3 Normal;
+in vec3 Tangent;
+in vec3 Bitangent;
+in vec4 ClipPos;
+
+#include "lights.glsl"
+
+uniform float time;
+
+// Scene mirrored under the surface, and the scene with its depth
+uniform sampler2D reflection_texture;
+uniform sampler2D refraction_texture;
+uniform sampler2D refraction_depth;
+uniform mat4 refraction_inverse_projection;
+uniform float near;
+uniform float far;
+
+uniform sampler2D normal_map;
+uniform float normal_tile_size;
+uniform float distortion;
+
+uniform vec3 shallow_color;
+uniform vec3 deep_color;
+uniform float depth_falloff;
+
+out vec4 FragColor;
+
+float linearize_depth(float depth) {
+  float z = depth * 2. - 1.;
+  return 2. * near * far / (far + near - z * (far - near));
+}
+
+// View distance to what the refraction pass drew at uv
+float refraction_distance(vec2 uv) {
+  vec3 ndc = vec3(uv, texture(refraction_depth, uv).r) * 2. - 1.;
+  vec4 view = refraction_inverse_projection * vec4(ndc, 1.);
+  return -view.z / view.w;
+}
+
+void main()
+{
+  // Two copies of the normal map scrolling in different directions break up
+  // the repetition of either one
+  vec2 uv = FragPos.xz / normal_tile_size;
+  vec3 detail1 = texture(normal_map, uv + time * vec2(0.03, 0.02)).rgb * 2. - 1.;
+  vec3 detail2 = texture(normal_map, uv * 1.7 + time * vec2(-0.02, 0.035)).rgb * 2. - 1.;
+  vec3 detail = normalize(detail1 + detail2);
+  vec3 normal = normalize(mat3(Tangent, Bitangent, Normal) * detail);
+
+  // Screen position, bent by the surface's slope
+  vec2 screen_uv = ClipPos.xy / ClipPos.w * 0.5 + 0.5;
+  vec2 offset = normal.xz * distortion;
+  vec2 refraction_uv = clamp(screen_uv + offset, 0.001, 0.999);
+
+  // The reflection was rendered looking up from under the surface, upside down
+  vec2 reflection_uv = clamp(vec2(screen_uv.x, 1. - screen_uv.y) + offset, 0.001, 0.999);
+
+  // How far the view ray travels through water before hitting something
+  float floor_distance = refraction_distance(refraction_uv);
+  float surface_distance = linearize_depth(gl_FragCoord.z);
+  float water_depth = max(floor_distance - surface_distance, 0.);
+  float murk = clamp(water_depth / depth_falloff, 0., 1.);
+
+  vec3 refracted = texture(refraction_texture, refraction_uv).rgb;
+  refracted = mix(refracted * shallow_color * 2., deep_color, murk);
+  vec3 reflected = texture(reflection_texture, reflection_uv).rgb;
+
+  // Schlick's approximation with water's reflectance head-on
+  vec3 view_dir = normalize(view_pos - FragPos);
+  float cos_theta = max(dot(view_dir, normal), 0.);
+  float fresnel = 0.02 + 0.98 * pow(1. - cos_theta, 5.);
+  vec3 color = mix(refracted, reflected, fresnel);
+
+  // Sun glints
+  for (int i = 0; i < dir_lights_len; ++i) {
+    vec3 reflect_dir = reflect(normalize(dir_lights[i].direction), normal);
+    color += dir_lights[i].specular * pow(max(dot(view_dir, reflect_dir), 0.), 256.);
+  }
+
+  // Fade in over the first few centimeters so shorelines aren't hard edges
+  FragColor = vec4(color, clamp(water_depth / 0.05, 0., 1.));
+}
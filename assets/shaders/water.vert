@@ -0,0 +1,52 @@
+layout (location = 0) in vec3 aPos;
+
+#define PI 3.14159265
+
+uniform mat4 model;
+uniform float time;
+// Must match MAX_WAVES in water.rs
+#define MAX_WAVES 8
+
+uniform Wave waves[MAX_WAVES];
+uniform int waves_len;
+
+out vec3 FragPos;
+out vec3 Normal;
+out vec3 Tangent;
+out vec3 Bitangent;
+out vec4 ClipPos;
+
+// Sum of Gerstner waves (GPU Gems 1, chapter 1), which move vertices in
+// circles so crests sharpen and troughs flatten
+void main()
+{
+  vec3 rest = vec3(model * vec4(aPos, 1.0));
+  vec3 position = rest;
+  vec3 tangent = vec3(1., 0., 0.);
+  vec3 bitangent = vec3(0., 0., 1.);
+
+  for (int i = 0; i < waves_len; ++i) {
+    Wave wave = waves[i];
+    float k = 2. * PI / wave.wavelength;
+
+    // Deep water waves travel at sqrt(g / k)
+    float speed = sqrt(9.8 / k);
+    vec2 d = normalize(wave.direction);
+    float f = k * (dot(d, rest.xz) - speed * time);
+    float amplitude = wave.steepness / k;
+    float s = sin(f);
+    float c = cos(f);
+
+    position += vec3(d.x * amplitude * c, amplitude * s, d.y * amplitude * c);
+    tangent += wave.steepness * vec3(-d.x * d.x * s, d.x * c, -d.x * d.y * s);
+    bitangent += wave.steepness * vec3(-d.x * d.y * s, d.y * c, -d.y * d.y * s);
+  }
+
+  FragPos = position;
+  Tangent = normalize(tangent);
+  Bitangent = normalize(bitangent);
+  Normal = normalize(cross(bitangent, tangent));
+
+  gl_Position = projection * view * vec4(position, 1.0);
+  ClipPos = gl_Position;
+}
@@ -177,8 +177,10 @@ pub fn bind_shader_block_def(input: TokenStream) -> TokenStream {
     shader_fields.join("\n")
   );
 
+  let block_name = ident.to_string();
   let imp = quote! {
     impl crate::shader::ShaderBlockDef for #ident {
+      const BLOCK_NAME: &'static str = #block_name;
       const BLOCK_DEF: &'static str = #type_def;
     }
   };
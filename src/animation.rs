@@ -0,0 +1,380 @@
+use std::rc::Rc;
+
+use crate::prelude::*;
+use glm::Quat;
+
+// Must match MAX_JOINTS in skinned.vert
+pub const MAX_JOINTS: usize = 64;
+
+// Local transform of a joint relative to its parent
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+  pub translation: Vec3,
+  pub rotation: Quat,
+  pub scale: Vec3,
+}
+
+// Normalized lerp that takes the shortest path, used instead of slerp since it's
+// cheaper and doesn't panic on opposite rotations
+fn nlerp(a: &Quat, b: &Quat, t: f32) -> Quat {
+  let b = if glm::quat_dot(a, b) < 0. { -*b } else { *b };
+  glm::quat_normalize(&(*a * (1. - t) + b * t))
+}
+
+impl Transform {
+  pub fn identity() -> Self {
+    Transform {
+      translation: glm::zero(),
+      rotation: glm::quat_identity(),
+      scale: glm::vec3(1., 1., 1.),
+    }
+  }
+
+  pub fn to_matrix(&self) -> Mat4 {
+    glm::translation(&self.translation)
+      * glm::quat_to_mat4(&self.rotation)
+      * glm::scaling(&self.scale)
+  }
+
+  pub fn lerp(&self, other: &Transform, t: f32) -> Transform {
+    Transform {
+      translation: glm::lerp(&self.translation, &other.translation, t),
+      rotation: nlerp(&self.rotation, &other.rotation, t),
+      scale: glm::lerp(&self.scale, &other.scale, t),
+    }
+  }
+}
+
+pub struct Joint {
+  pub name: String,
+  pub parent: Option<usize>,
+
+  // Transform used when no clip animates this joint
+  pub rest: Transform,
+
+  // Maps mesh space into the joint's space at bind time
+  pub inverse_bind: Mat4,
+}
+
+// Joints must be ordered so that parents come before their children
+pub struct Skeleton {
+  pub joints: Vec<Joint>,
+}
+
+impl Skeleton {
+  pub fn new(joints: Vec<Joint>) -> Result<Self> {
+    if joints.len() > MAX_JOINTS {
+      bail!(
+        "Skeleton has {} joints, max is {}",
+        joints.len(),
+        MAX_JOINTS
+      );
+    }
+    for (i, joint) in joints.iter().enumerate() {
+      if let Some(parent) = joint.parent {
+        if parent >= i {
+          bail!("Joint {} appears before its parent {}", joint.name, parent);
+        }
+      }
+    }
+    Ok(Skeleton { joints })
+  }
+
+  pub fn rest_pose(&self) -> Vec<Transform> {
+    self.joints.iter().map(|joint| joint.rest).collect()
+  }
+
+  // Convert a local pose into the matrices uploaded to the skinning shader
+  pub fn skinning_matrices(&self, pose: &[Transform]) -> Vec<Mat4> {
+    let mut globals: Vec<Mat4> = Vec::with_capacity(self.joints.len());
+    for (joint, local) in self.joints.iter().zip(pose.iter()) {
+      let local = local.to_matrix();
+      let global = match joint.parent {
+        Some(parent) => globals[parent] * local,
+        None => local,
+      };
+      globals.push(global);
+    }
+
+    globals
+      .iter()
+      .zip(self.joints.iter())
+      .map(|(global, joint)| global * joint.inverse_bind)
+      .collect()
+  }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Interpolation {
+  Step,
+  Linear,
+}
+
+pub trait Interpolate: Copy {
+  fn interpolate(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Interpolate for Vec3 {
+  fn interpolate(&self, other: &Self, t: f32) -> Self {
+    glm::lerp(self, other, t)
+  }
+}
+
+impl Interpolate for Quat {
+  fn interpolate(&self, other: &Self, t: f32) -> Self {
+    nlerp(self, other, t)
+  }
+}
+
+// Keyframes for a single property of a joint. There's at least one, with
+// strictly increasing times.
+pub struct Channel<T> {
+  times: Vec<f32>,
+  values: Vec<T>,
+  interpolation: Interpolation,
+}
+
+impl<T: Interpolate> Channel<T> {
+  pub fn new(keyframes: Vec<(f32, T)>, interpolation: Interpolation) -> Result<Self> {
+    if keyframes.is_empty() {
+      bail!("Channel has no keyframes");
+    }
+    for pair in keyframes.windows(2) {
+      let (t0, t1) = (pair[0].0, pair[1].0);
+      if t0.is_nan() || t1.is_nan() || t0 >= t1 {
+        bail!(
+          "Keyframe times must increase, but {} is followed by {}",
+          t0,
+          t1
+        );
+      }
+    }
+
+    let (times, values) = keyframes.into_iter().unzip();
+    Ok(Channel {
+      times,
+      values,
+      interpolation,
+    })
+  }
+
+  fn duration(&self) -> f32 {
+    self.times.last().cloned().unwrap_or(0.)
+  }
+
+  fn sample(&self, time: f32) -> T {
+    // Index of the first keyframe after time
+    let next = self.times.iter().position(|t| *t > time);
+    match next {
+      Some(0) => self.values[0],
+      None => *self.values.last().unwrap(),
+      Some(i) => match self.interpolation {
+        Interpolation::Step => self.values[i - 1],
+        Interpolation::Linear => {
+          let (t0, t1) = (self.times[i - 1], self.times[i]);
+          let t = (time - t0) / (t1 - t0);
+          self.values[i - 1].interpolate(&self.values[i], t)
+        }
+      },
+    }
+  }
+}
+
+pub struct JointTrack {
+  pub joint: usize,
+  pub translation: Option<Channel<Vec3>>,
+  pub rotation: Option<Channel<Quat>>,
+  pub scale: Option<Channel<Vec3>>,
+}
+
+pub struct AnimationClip {
+  pub name: String,
+  pub duration: f32,
+  pub tracks: Vec<JointTrack>,
+}
+
+impl AnimationClip {
+  pub fn new(name: impl Into<String>, tracks: Vec<JointTrack>) -> Self {
+    let duration = tracks
+      .iter()
+      .flat_map(|track| {
+        vec![
+          track.translation.as_ref().map(|c| c.duration()),
+          track.rotation.as_ref().map(|c| c.duration()),
+          track.scale.as_ref().map(|c| c.duration()),
+        ]
+      })
+      .filter_map(|d| d)
+      .fold(0., f32::max);
+    AnimationClip {
+      name: name.into(),
+      duration,
+      tracks,
+    }
+  }
+
+  // Overwrite the joints animated by this clip in the given pose
+  pub fn sample(&self, time: f32, pose: &mut [Transform]) {
+    for track in &self.tracks {
+      let transform = &mut pose[track.joint];
+      if let Some(channel) = &track.translation {
+        transform.translation = channel.sample(time);
+      }
+      if let Some(channel) = &track.rotation {
+        transform.rotation = channel.sample(time);
+      }
+      if let Some(channel) = &track.scale {
+        transform.scale = channel.sample(time);
+      }
+    }
+  }
+}
+
+struct PlayingClip {
+  clip: Rc<AnimationClip>,
+  time: f32,
+  speed: f32,
+  looping: bool,
+  weight: f32,
+
+  // Weight moves towards target at fade_rate per second. Clips fading to 0 are removed.
+  target_weight: f32,
+  fade_rate: f32,
+}
+
+// Plays and blends clips on a skeleton, producing joint matrices each frame
+pub struct Animator {
+  skeleton: Rc<Skeleton>,
+  playing: Vec<PlayingClip>,
+  joint_matrices: Vec<Mat4>,
+
+  // Matrices as of the last drawn frame, for motion vectors
+  prev_joint_matrices: Vec<Mat4>,
+}
+
+impl Animator {
+  pub fn new(skeleton: Rc<Skeleton>) -> Self {
+    let joint_matrices = skeleton.skinning_matrices(&skeleton.rest_pose());
+    Animator {
+      skeleton,
+      playing: vec![],
+      prev_joint_matrices: joint_matrices.clone(),
+      joint_matrices,
+    }
+  }
+
+  // Cross-fade from every playing clip to this one over fade seconds
+  pub fn play(&mut self, clip: Rc<AnimationClip>, looping: bool, fade: f32) {
+    for playing in self.playing.iter_mut() {
+      playing.target_weight = 0.;
+      playing.fade_rate = Self::fade_rate(playing.weight, fade);
+    }
+    self.blend(clip, 1., looping, fade);
+  }
+
+  // Layer a clip on top of the playing ones with the given blend weight
+  pub fn blend(&mut self, clip: Rc<AnimationClip>, weight: f32, looping: bool, fade: f32) {
+    self.playing.push(PlayingClip {
+      clip,
+      time: 0.,
+      speed: 1.,
+      looping,
+      weight: if fade > 0. { 0. } else { weight },
+      target_weight: weight,
+      fade_rate: Self::fade_rate(weight, fade),
+    });
+  }
+
+  pub fn stop(&mut self, name: &str, fade: f32) {
+    for playing in self.playing.iter_mut() {
+      if playing.clip.name == name {
+        playing.target_weight = 0.;
+        playing.fade_rate = Self::fade_rate(playing.weight, fade);
+      }
+    }
+  }
+
+  pub fn set_weight(&mut self, name: &str, weight: f32) {
+    for playing in self.playing.iter_mut() {
+      if playing.clip.name == name {
+        playing.weight = weight;
+        playing.target_weight = weight;
+      }
+    }
+  }
+
+  pub fn set_speed(&mut self, name: &str, speed: f32) {
+    for playing in self.playing.iter_mut() {
+      if playing.clip.name == name {
+        playing.speed = speed;
+      }
+    }
+  }
+
+  fn fade_rate(weight: f32, fade: f32) -> f32 {
+    if fade > 0. {
+      weight.max(0.01) / fade
+    } else {
+      f32::INFINITY
+    }
+  }
+
+  pub fn update(&mut self, dt: f32) {
+    // Advance clip times and fades
+    for playing in self.playing.iter_mut() {
+      playing.time += dt * playing.speed;
+      let duration = playing.clip.duration;
+      if duration > 0. {
+        playing.time = if playing.looping {
+          playing.time.rem_euclid(duration)
+        } else {
+          playing.time.min(duration)
+        };
+      }
+
+      let step = playing.fade_rate * dt;
+      playing.weight = if playing.weight < playing.target_weight {
+        (playing.weight + step).min(playing.target_weight)
+      } else {
+        (playing.weight - step).max(playing.target_weight)
+      };
+    }
+    self
+      .playing
+      .retain(|playing| playing.weight > 0. || playing.target_weight > 0.);
+
+    // Blend each clip's pose into the result, weighting by the running total
+    // so that the final pose is a normalized average
+    let rest = self.skeleton.rest_pose();
+    let mut pose = rest.clone();
+    let mut total_weight = 0.;
+    for playing in &self.playing {
+      if playing.weight <= 0. {
+        continue;
+      }
+
+      let mut clip_pose = rest.clone();
+      playing.clip.sample(playing.time, &mut clip_pose);
+
+      total_weight += playing.weight;
+      let t = playing.weight / total_weight;
+      for (joint, clip_joint) in pose.iter_mut().zip(clip_pose.iter()) {
+        *joint = joint.lerp(clip_joint, t);
+      }
+    }
+
+    self.joint_matrices = self.skeleton.skinning_matrices(&pose);
+  }
+
+  pub fn joint_matrices(&self) -> &Vec<Mat4> {
+    &self.joint_matrices
+  }
+
+  pub fn prev_joint_matrices(&self) -> &Vec<Mat4> {
+    &self.prev_joint_matrices
+  }
+
+  pub fn end_frame(&mut self) {
+    self.prev_joint_matrices.clone_from(&self.joint_matrices);
+  }
+}
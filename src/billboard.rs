@@ -0,0 +1,144 @@
+use crate::{
+  geometry::Geometry,
+  mesh::Mesh,
+  prelude::*,
+  shader::{ActiveShader, Shader},
+  texture::Texture,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BillboardMode {
+  // Always faces the camera head on
+  Spherical,
+
+  // Only turns about the world's up axis, so it stays upright like a plant
+  Cylindrical,
+}
+
+// Frames laid out left to right, then top to bottom, in a single texture
+#[derive(Clone, Copy, Debug)]
+pub struct SpriteSheet {
+  pub columns: u32,
+  pub rows: u32,
+
+  // May be less than columns * rows if the last row isn't full
+  pub frames: u32,
+  pub fps: f32,
+}
+
+impl SpriteSheet {
+  // UV offset and scale of the frame showing at time
+  fn frame_rect(&self, time: f32) -> Vec4 {
+    let frame = (time.max(0.) * self.fps) as u32 % self.frames.max(1);
+    let (column, row) = (frame % self.columns, frame / self.columns);
+    let scale = glm::vec2(1. / self.columns as f32, 1. / self.rows as f32);
+
+    // Textures are flipped on load, so the first row is at the top in v
+    glm::vec4(
+      column as f32 * scale.x,
+      1. - (row + 1) as f32 * scale.y,
+      scale.x,
+      scale.y,
+    )
+  }
+}
+
+// A textured quad centered on position, turned toward the camera in the
+// vertex shader
+#[derive(Clone)]
+pub struct Billboard {
+  pub position: Vec3,
+  pub size: Vec2,
+  pub mode: BillboardMode,
+
+  // Whether the scene's directional lights shade it, off for things that glow
+  pub lit: bool,
+  texture: Texture,
+  sprite_sheet: Option<SpriteSheet>,
+}
+
+impl Billboard {
+  pub fn new(texture: Texture, position: Vec3, size: Vec2) -> Self {
+    Billboard {
+      position,
+      size,
+      mode: BillboardMode::Spherical,
+      lit: true,
+      texture,
+      sprite_sheet: None,
+    }
+  }
+
+  pub fn with_mode(mut self, mode: BillboardMode) -> Self {
+    self.mode = mode;
+    self
+  }
+
+  pub fn with_lighting(mut self, lit: bool) -> Self {
+    self.lit = lit;
+    self
+  }
+
+  pub fn with_sprite_sheet(mut self, sprite_sheet: SpriteSheet) -> Self {
+    self.sprite_sheet = Some(sprite_sheet);
+    self
+  }
+}
+
+// Shader and quad shared by every billboard in a scene
+pub struct BillboardRenderer {
+  shader: Shader,
+  quad: Mesh,
+}
+
+impl BillboardRenderer {
+  pub async unsafe fn load(gl: &Context) -> Result<Self> {
+    let shader = Shader::load(
+      gl,
+      "assets/shaders/billboard.vert",
+      "assets/shaders/billboard.frag",
+      None,
+    )
+    .await?;
+
+    // The shader reads corners from x and z of this plane
+    let quad = Geometry::Plane {
+      length: 1.,
+      width: 1.,
+      normal: glm::vec3(0., 0., 1.),
+    }
+    .to_mesh(gl, None)?;
+
+    Ok(BillboardRenderer { shader, quad })
+  }
+
+  pub fn shader(&self) -> &Shader {
+    &self.shader
+  }
+
+  // Draws with the billboard shader, which must already be active with
+  // lights bound
+  pub unsafe fn draw(
+    &self,
+    gl: &Context,
+    shader: &mut ActiveShader,
+    billboard: &Billboard,
+    time: f32,
+  ) {
+    shader.bind_uniform(gl, "center", &billboard.position);
+    shader.bind_uniform(gl, "size", &billboard.size);
+    shader.bind_uniform(
+      gl,
+      "cylindrical",
+      &(billboard.mode == BillboardMode::Cylindrical),
+    );
+    shader.bind_uniform(gl, "lit", &billboard.lit);
+    let frame_rect = match billboard.sprite_sheet.as_ref() {
+      Some(sheet) => sheet.frame_rect(time),
+      None => glm::vec4(0., 0., 1., 1.),
+    };
+    shader.bind_uniform(gl, "frame_rect", &frame_rect);
+    shader.bind_uniform(gl, "sprite", &billboard.texture);
+    self.quad.draw(gl, shader);
+  }
+}
@@ -1,6 +1,7 @@
 use crate::{prelude::*, user_inputs::UserInputs};
 use winit::event::VirtualKeyCode as Key;
 
+#[derive(Clone)]
 pub struct Camera {
   pub pos: Vec3,
   pub up: Vec3,
@@ -9,21 +10,31 @@ pub struct Camera {
   pub pitch: f32,
   pub yaw: f32,
   pub projection: Mat4,
+
+  // Sub-pixel offset in NDC added to the projection for temporal anti-aliasing
+  pub jitter: Vec2,
+
+  // Unjittered view-projection of the last drawn frame, for motion vectors
+  prev_view_projection: Mat4,
 }
 
 impl Camera {
   pub fn new(pos: Vec3, projection: Mat4, look_at: Vec3) -> Self {
     let look_dir = glm::normalize(&(pos - look_at));
 
-    Camera {
+    let mut camera = Camera {
       pos,
       up: glm::vec3(0., 1., 0.),
       speed: 2.5,
       sensitivity: 0.25,
-      yaw: f32::atan2(look_dir.x, look_dir.z).to_degrees(),
+      yaw: f32::atan2(-look_dir.x, look_dir.z).to_degrees(),
       pitch: f32::asin(-look_dir.y).to_degrees(),
       projection,
-    }
+      jitter: glm::zero(),
+      prev_view_projection: glm::identity(),
+    };
+    camera.prev_view_projection = camera.view_projection();
+    camera
   }
 
   pub fn front(&self) -> Vec3 {
@@ -43,6 +54,30 @@ impl Camera {
     glm::look_at(&self.pos, &(self.pos + self.front()), &self.up)
   }
 
+  pub fn view_projection(&self) -> Mat4 {
+    self.projection * self.view_matrix()
+  }
+
+  pub fn jittered_projection(&self) -> Mat4 {
+    // x_ndc = x_clip / -z_view, so shifting by jitter in NDC means subtracting it
+    // from the terms multiplied by z_view
+    let mut projection = self.projection;
+    projection[(0, 2)] -= self.jitter.x;
+    projection[(1, 2)] -= self.jitter.y;
+    projection
+  }
+
+  // Remember this frame's view-projection as the previous one for the next frame
+  pub fn end_frame(&mut self) {
+    self.prev_view_projection = self.view_projection();
+  }
+
+  // Recover the clip planes from a perspective projection matrix
+  pub fn near_far(&self) -> (f32, f32) {
+    let (a, b) = (self.projection[(2, 2)], self.projection[(2, 3)]);
+    (b / (a - 1.), b / (a + 1.))
+  }
+
   pub fn right(&self) -> Vec3 {
     glm::cross(&self.front(), &self.up)
   }
@@ -75,7 +110,9 @@ impl Camera {
     CameraBlock {
       view_pos: self.pos.to_std140(),
       view: self.view_matrix().to_std140(),
-      projection: self.projection.to_std140(),
+      projection: self.jittered_projection().to_std140(),
+      view_projection: self.view_projection().to_std140(),
+      prev_view_projection: self.prev_view_projection.to_std140(),
     }
   }
 }
@@ -86,4 +123,45 @@ pub struct CameraBlock {
   view_pos: std140::vec3,
   view: std140::mat4x4,
   projection: std140::mat4x4,
+
+  // Both without jitter
+  view_projection: std140::mat4x4,
+  prev_view_projection: std140::mat4x4,
+}
+
+// Clip planes of a view-projection matrix, as (normal, distance) with the
+// normals pointing inside
+pub struct Frustum {
+  planes: [Vec4; 6],
+}
+
+impl Frustum {
+  // Multiplying in a model matrix gives the frustum in that model's space
+  pub fn from_matrix(matrix: &Mat4) -> Self {
+    let row = |i: usize| -> Vec4 { matrix.row(i).transpose() };
+    Frustum {
+      planes: [
+        row(3) + row(0),
+        row(3) - row(0),
+        row(3) + row(1),
+        row(3) - row(1),
+        row(3) + row(2),
+        row(3) - row(2),
+      ],
+    }
+  }
+
+  // False only if the box is entirely outside one plane, so some boxes near
+  // the frustum's corners pass anyway
+  pub fn intersects_aabb(&self, min: &Vec3, max: &Vec3) -> bool {
+    self.planes.iter().all(|plane| {
+      // Corner furthest along the plane's normal
+      let corner = glm::vec3(
+        if plane.x >= 0. { max.x } else { min.x },
+        if plane.y >= 0. { max.y } else { min.y },
+        if plane.z >= 0. { max.z } else { min.z },
+      );
+      plane.xyz().dot(&corner) + plane.w >= 0.
+    })
+  }
 }
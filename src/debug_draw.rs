@@ -0,0 +1,262 @@
+use std::mem::size_of;
+
+use crate::{
+  light::{PointLight, SpotLight},
+  prelude::*,
+  shader::Shader,
+  stats,
+};
+
+#[repr(C)]
+#[derive(Clone, Debug)]
+struct DebugVertex {
+  position: Vec3,
+  color: Vec4,
+}
+
+// Number of segments used to approximate circles
+const CIRCLE_SEGMENTS: usize = 24;
+
+// Two unit vectors perpendicular to dir and each other
+fn perpendicular_axes(dir: &Vec3) -> (Vec3, Vec3) {
+  let up = if dir.y.abs() < 0.999 {
+    glm::vec3(0., 1., 0.)
+  } else {
+    glm::vec3(1., 0., 0.)
+  };
+  let u = glm::normalize(&glm::cross(&up, dir));
+  let v = glm::cross(dir, &u);
+  (u, v)
+}
+
+// Immediate-mode line renderer. Shapes are queued during update and drawn
+// (then forgotten) by the next call to flush.
+pub struct DebugDraw {
+  pub enabled: bool,
+
+  // Lines are split by whether they should be hidden behind scene geometry
+  depth_tested: Vec<DebugVertex>,
+  overlay: Vec<DebugVertex>,
+
+  shader: Shader,
+  vertex_array: GlVertexArray,
+  vertex_buffer: GlBuffer,
+}
+
+impl DebugDraw {
+  pub async unsafe fn load(gl: &Context) -> Result<Self> {
+    let shader = Shader::load(
+      gl,
+      "assets/shaders/debug.vert",
+      "assets/shaders/debug.frag",
+      None,
+    )
+    .await?;
+
+    let vertex_array = gl.create_vertex_array().map_err(Error::msg)?;
+    gl.bind_vertex_array(Some(vertex_array));
+
+    let vertex_buffer = gl.create_buffer().map_err(Error::msg)?;
+    gl.bind_buffer(glow::ARRAY_BUFFER, Some(vertex_buffer));
+
+    let size_f32 = size_of::<f32>() as i32;
+    let sizes = [3, 4];
+    let stride = sizes.iter().sum::<i32>() * size_f32;
+
+    let mut offset = 0;
+    for (i, size) in sizes.iter().enumerate() {
+      gl.enable_vertex_attrib_array(i as u32);
+      gl.vertex_attrib_pointer_f32(
+        i as u32,
+        *size,
+        glow::FLOAT,
+        false,
+        stride,
+        offset * size_f32,
+      );
+      offset += size;
+    }
+
+    gl.bind_vertex_array(None);
+
+    Ok(DebugDraw {
+      enabled: false,
+      depth_tested: vec![],
+      overlay: vec![],
+      shader,
+      vertex_array,
+      vertex_buffer,
+    })
+  }
+
+  pub fn shader(&self) -> &Shader {
+    &self.shader
+  }
+
+  pub fn clear(&mut self) {
+    self.depth_tested.clear();
+    self.overlay.clear();
+  }
+
+  pub fn line(&mut self, start: Vec3, end: Vec3, color: Vec4, depth_test: bool) {
+    if !self.enabled {
+      return;
+    }
+
+    let vertices = if depth_test {
+      &mut self.depth_tested
+    } else {
+      &mut self.overlay
+    };
+    vertices.push(DebugVertex {
+      position: start,
+      color,
+    });
+    vertices.push(DebugVertex {
+      position: end,
+      color,
+    });
+  }
+
+  pub fn aabb(&mut self, min: Vec3, max: Vec3, color: Vec4, depth_test: bool) {
+    let corner = |i: usize| {
+      glm::vec3(
+        if i & 1 == 0 { min.x } else { max.x },
+        if i & 2 == 0 { min.y } else { max.y },
+        if i & 4 == 0 { min.z } else { max.z },
+      )
+    };
+    self.box_edges(corner, color, depth_test);
+  }
+
+  // Draws the 12 edges of a box whose 8 corners are indexed by 3 bits (x, y, z)
+  fn box_edges(&mut self, corner: impl Fn(usize) -> Vec3, color: Vec4, depth_test: bool) {
+    for i in 0..8 {
+      for bit in &[1, 2, 4] {
+        if i & bit == 0 {
+          self.line(corner(i), corner(i | bit), color, depth_test);
+        }
+      }
+    }
+  }
+
+  pub fn circle(&mut self, center: Vec3, normal: Vec3, radius: f32, color: Vec4, depth_test: bool) {
+    let normal = glm::normalize(&normal);
+    let (u, v) = perpendicular_axes(&normal);
+    let (u, v) = (u * radius, v * radius);
+
+    let point = |i: usize| {
+      let angle = (i as f32) / (CIRCLE_SEGMENTS as f32) * std::f32::consts::PI * 2.;
+      center + u * angle.cos() + v * angle.sin()
+    };
+    for i in 0..CIRCLE_SEGMENTS {
+      self.line(point(i), point(i + 1), color, depth_test);
+    }
+  }
+
+  pub fn sphere(&mut self, center: Vec3, radius: f32, color: Vec4, depth_test: bool) {
+    for axis in vec![
+      glm::vec3(1., 0., 0.),
+      glm::vec3(0., 1., 0.),
+      glm::vec3(0., 0., 1.),
+    ] {
+      self.circle(center, axis, radius, color, depth_test);
+    }
+  }
+
+  // Draws the volume visible to a camera with the given projection * view matrix
+  pub fn frustum(&mut self, view_projection: &Mat4, color: Vec4, depth_test: bool) {
+    let inverse = glm::inverse(view_projection);
+    let corner = |i: usize| {
+      let ndc = glm::vec4(
+        if i & 1 == 0 { -1. } else { 1. },
+        if i & 2 == 0 { -1. } else { 1. },
+        if i & 4 == 0 { -1. } else { 1. },
+        1.,
+      );
+      let world = inverse * ndc;
+      world.xyz() / world.w
+    };
+    self.box_edges(corner, color, depth_test);
+  }
+
+  // Draws the x/y/z axes of a transform in red/green/blue
+  pub fn axes(&mut self, transform: &Mat4, size: f32, depth_test: bool) {
+    let origin = (transform * glm::vec4(0., 0., 0., 1.)).xyz();
+    let axes = vec![
+      (glm::vec4(size, 0., 0., 1.), glm::vec4(1., 0., 0., 1.)),
+      (glm::vec4(0., size, 0., 1.), glm::vec4(0., 1., 0., 1.)),
+      (glm::vec4(0., 0., size, 1.), glm::vec4(0., 0., 1., 1.)),
+    ];
+    for (axis, color) in axes {
+      self.line(origin, (transform * axis).xyz(), color, depth_test);
+    }
+  }
+
+  pub fn arrow(&mut self, start: Vec3, end: Vec3, color: Vec4, depth_test: bool) {
+    self.line(start, end, color, depth_test);
+
+    let dir = end - start;
+    let length = glm::length(&dir);
+    if length == 0. {
+      return;
+    }
+
+    // Four head lines angled back from the tip
+    let dir = dir / length;
+    let (u, v) = perpendicular_axes(&dir);
+    let head = length * 0.2;
+    for side in vec![u, -u, v, -v] {
+      self.line(end, end - dir * head + side * head * 0.5, color, depth_test);
+    }
+  }
+
+  pub fn point_light(&mut self, light: &PointLight) {
+    let color = glm::vec4(light.diffuse.x, light.diffuse.y, light.diffuse.z, 1.);
+    self.sphere(light.position, 0.1, color, false);
+  }
+
+  // Draws the outer cut-off cone of a spot light out to the given distance
+  pub fn spot_light(&mut self, light: &SpotLight, range: f32) {
+    let color = glm::vec4(light.diffuse.x, light.diffuse.y, light.diffuse.z, 1.);
+    let dir = glm::normalize(&light.direction);
+    let end = light.position + dir * range;
+    let radius = range * light.outer_cut_off.acos().tan();
+
+    self.arrow(light.position, end, color, true);
+    self.circle(end, dir, radius, color, true);
+
+    let (u, v) = perpendicular_axes(&dir);
+    for side in vec![u, -u, v, -v] {
+      self.line(light.position, end + side * radius, color, true);
+    }
+  }
+
+  pub unsafe fn flush(&mut self, gl: &Context) {
+    if self.enabled {
+      self.shader.activate(gl);
+      gl.bind_vertex_array(Some(self.vertex_array));
+      gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vertex_buffer));
+
+      for (vertices, depth_test) in vec![(&self.depth_tested, true), (&self.overlay, false)] {
+        if vertices.is_empty() {
+          continue;
+        }
+
+        let (_, vertices_bytes, _) = vertices.align_to::<u8>();
+        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, vertices_bytes, glow::STREAM_DRAW);
+
+        if !depth_test {
+          gl.disable(glow::DEPTH_TEST);
+        }
+        gl.draw_arrays(glow::LINES, 0, vertices.len() as i32);
+        stats::record_draw(0);
+        gl.enable(glow::DEPTH_TEST);
+      }
+
+      gl.bind_vertex_array(None);
+    }
+
+    self.clear();
+  }
+}
@@ -3,6 +3,8 @@ use crate::{
   mesh::{Mesh, Vertex},
   prelude::*,
 };
+use image::GrayImage;
+use std::{ops::Range, rc::Rc};
 
 pub enum Geometry {
   Cube {
@@ -15,6 +17,47 @@ pub enum Geometry {
     width: f32,
     normal: Vec3,
   },
+
+  // Plane facing +y split into cells x cells quads, for surfaces displaced
+  // per vertex
+  Grid {
+    length: f32,
+    width: f32,
+    cells: u32,
+  },
+
+  // Heightfield from a grayscale image, centered on the origin. Vertices sit on
+  // the pixels in x_range and z_range including both ends, so terrain split
+  // into chunks shares vertices along chunk edges.
+  Terrain {
+    heightmap: Rc<GrayImage>,
+
+    // Extent of the whole heightmap on x and z
+    size: Vec2,
+
+    // Height of a white pixel
+    height_scale: f32,
+    x_range: Range<u32>,
+    z_range: Range<u32>,
+  },
+}
+
+fn terrain_height(heightmap: &GrayImage, height_scale: f32, x: u32, z: u32) -> f32 {
+  heightmap.get_pixel(x, z)[0] as f32 / 255. * height_scale
+}
+
+// Slopes dh/dx and dh/dz from central differences over the whole heightmap,
+// so vertices on chunk edges get the same normal from either chunk
+fn terrain_slope(heightmap: &GrayImage, size: Vec2, height_scale: f32, x: u32, z: u32) -> Vec2 {
+  let (width, height) = heightmap.dimensions();
+  let spacing = glm::vec2(size.x / (width - 1) as f32, size.y / (height - 1) as f32);
+  let (x0, x1) = (x.saturating_sub(1), (x + 1).min(width - 1));
+  let (z0, z1) = (z.saturating_sub(1), (z + 1).min(height - 1));
+  let h = |x, z| terrain_height(heightmap, height_scale, x, z);
+  glm::vec2(
+    (h(x1, z) - h(x0, z)) / ((x1 - x0) as f32 * spacing.x),
+    (h(x, z1) - h(x, z0)) / ((z1 - z0) as f32 * spacing.y),
+  )
 }
 
 impl Geometry {
@@ -99,11 +142,107 @@ impl Geometry {
         let indices = vec![0, 1, 2, 1, 3, 2, 0, 2, 1, 1, 2, 3];
         (vertices, indices)
       }
+
+      Geometry::Grid {
+        length,
+        width,
+        cells,
+      } => {
+        let mut vertices = vec![];
+        for z in 0..=cells {
+          for x in 0..=cells {
+            let u = x as f32 / cells as f32;
+            let v = z as f32 / cells as f32;
+            vertices.push(Vertex {
+              position: glm::vec3((u - 0.5) * length, 0., (v - 0.5) * width),
+              normal: glm::vec3(0., 1., 0.),
+              tex_coords: glm::vec2(u, 1. - v),
+            });
+          }
+        }
+
+        let row = cells + 1;
+        let mut indices = vec![];
+        for z in 0..cells {
+          for x in 0..cells {
+            let i = z * row + x;
+            indices.extend_from_slice(&[i, i + row, i + 1, i + 1, i + row, i + row + 1]);
+          }
+        }
+        (vertices, indices)
+      }
+
+      Geometry::Terrain {
+        ref heightmap,
+        size,
+        height_scale,
+        ref x_range,
+        ref z_range,
+      } => {
+        let (width, height) = heightmap.dimensions();
+        let mut vertices = vec![];
+        for z in z_range.start..=z_range.end {
+          for x in x_range.start..=x_range.end {
+            let u = x as f32 / (width - 1) as f32;
+            let v = z as f32 / (height - 1) as f32;
+            let slope = terrain_slope(heightmap, size, height_scale, x, z);
+            vertices.push(Vertex {
+              position: glm::vec3(
+                (u - 0.5) * size.x,
+                terrain_height(heightmap, height_scale, x, z),
+                (v - 0.5) * size.y,
+              ),
+              normal: glm::normalize(&glm::vec3(-slope.x, 1., -slope.y)),
+
+              // Image rows start at the top, textures at the bottom
+              tex_coords: glm::vec2(u, 1. - v),
+            });
+          }
+        }
+
+        let row = x_range.end - x_range.start + 1;
+        let mut indices = vec![];
+        for z in 0..(z_range.end - z_range.start) {
+          for x in 0..(x_range.end - x_range.start) {
+            let i = z * row + x;
+            indices.extend_from_slice(&[i, i + row, i + 1, i + 1, i + row, i + row + 1]);
+          }
+        }
+        (vertices, indices)
+      }
+    }
+  }
+
+  // Tangents for geometry that needs a tangent frame, in the order of to_vertices_indices
+  pub fn to_tangents(&self) -> Option<Vec<Vec3>> {
+    match *self {
+      Geometry::Terrain {
+        ref heightmap,
+        size,
+        height_scale,
+        ref x_range,
+        ref z_range,
+      } => {
+        let mut tangents = vec![];
+        for z in z_range.start..=z_range.end {
+          for x in x_range.start..=x_range.end {
+            // u follows x, so the tangent climbs the x slope
+            let slope = terrain_slope(heightmap, size, height_scale, x, z);
+            tangents.push(glm::normalize(&glm::vec3(1., slope.x, 0.)));
+          }
+        }
+        Some(tangents)
+      }
+      _ => None,
     }
   }
 
   pub unsafe fn to_mesh(&self, gl: &Context, material: Option<Material>) -> Result<Mesh> {
     let (vertices, indices) = self.to_vertices_indices();
-    Mesh::new(gl, vertices, indices, material)
+    let mesh = Mesh::new(gl, vertices, indices, material)?;
+    match self.to_tangents() {
+      Some(tangents) => mesh.with_tangents(gl, tangents),
+      None => Ok(mesh),
+    }
   }
 }
@@ -0,0 +1,410 @@
+// Golden-image regression tests. Reference scenes are rendered through a
+// headless GL context (Mesa's software OSMesa/llvmpipe on CI) and compared
+// against the PNGs in tests/golden. A missing golden fails the test, and
+// UPDATE_GOLDEN=1 writes all of them, for new scenes or after an intended
+// change. On failure the actual image (and a diff) is left in target/golden.
+
+use crate::{
+  camera::{Camera, CameraBlock},
+  geometry::Geometry,
+  headless::HeadlessContext,
+  light::{DirLight, PointLight, SpotLight},
+  material::Material,
+  mesh::Mesh,
+  post_process::PostStack,
+  prelude::*,
+  screen_capture::Framebuffer,
+  screenshot,
+  shader::{Shader, UniformBlock},
+  text::{Font, Text},
+  texture::TextureBuilder,
+};
+use image::{Rgba, RgbaImage};
+use std::{collections::HashMap, iter, path::Path};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 128;
+const GOLDEN_DIR: &str = "tests/golden";
+const OUTPUT_DIR: &str = "target/golden";
+
+// Perceptual distance above which a pixel counts as changed, from 0 to 1
+const PIXEL_THRESHOLD: f32 = 0.1;
+
+// Fraction of changed pixels allowed, to absorb rasterization differences
+// between GL implementations
+const MAX_CHANGED: f32 = 0.005;
+
+// Pixel in YIQ space, blended onto white so that alpha differences show
+fn yiq(pixel: &Rgba<u8>) -> Vec3 {
+  let alpha = pixel[3] as f32 / 255.;
+  let blend = |c: u8| 255. + (c as f32 - 255.) * alpha;
+  let (r, g, b) = (blend(pixel[0]), blend(pixel[1]), blend(pixel[2]));
+  glm::vec3(
+    0.29889531 * r + 0.58662247 * g + 0.11448223 * b,
+    0.59597799 * r - 0.2741761 * g - 0.32180189 * b,
+    0.21147017 * r - 0.52261711 * g + 0.31114694 * b,
+  )
+}
+
+// Squared perceptual color difference from 0 to 1, weighted like pixelmatch
+fn color_delta(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+  let d = yiq(a) - yiq(b);
+  (0.5053 * d.x * d.x + 0.299 * d.y * d.y + 0.1957 * d.z * d.z) / 35215.
+}
+
+// Returns the number of changed pixels and an image of the expected output,
+// faded, with changed pixels in red
+fn compare(expected: &RgbaImage, actual: &RgbaImage) -> (usize, RgbaImage) {
+  let mut changed = 0;
+  let mut diff = RgbaImage::new(actual.width(), actual.height());
+  for (x, y, pixel) in actual.enumerate_pixels() {
+    let expected = expected.get_pixel(x, y);
+    let color = if color_delta(expected, pixel) > PIXEL_THRESHOLD * PIXEL_THRESHOLD {
+      changed += 1;
+      Rgba([255, 0, 0, 255])
+    } else {
+      let luma = (255. - (255. - yiq(expected).x) * 0.1) as u8;
+      Rgba([luma, luma, luma, 255])
+    };
+    diff.put_pixel(x, y, color);
+  }
+  (changed, diff)
+}
+
+// Returns a description of the failure if actual doesn't match the golden
+fn check_golden(name: &str, actual: &RgbaImage) -> Result<Option<String>> {
+  let golden_path = Path::new(GOLDEN_DIR).join(format!("{}.png", name));
+  let actual_path = Path::new(OUTPUT_DIR).join(format!("{}.png", name));
+  if std::env::var_os("UPDATE_GOLDEN").is_some() {
+    screenshot::save_png(actual, &golden_path)?;
+    println!("Wrote golden {}", golden_path.display());
+    return Ok(None);
+  }
+  if !golden_path.exists() {
+    screenshot::save_png(actual, &actual_path)?;
+    return Ok(Some(format!(
+      "{}: no golden at {}, rendered {} (run with UPDATE_GOLDEN=1 to accept it)",
+      name,
+      golden_path.display(),
+      actual_path.display()
+    )));
+  }
+
+  let expected = image::open(&golden_path)
+    .with_context(|| format!("Failed to read {}", golden_path.display()))?
+    .into_rgba8();
+  if expected.dimensions() != actual.dimensions() {
+    return Ok(Some(format!(
+      "{}: expected {:?} pixels but rendered {:?}",
+      name,
+      expected.dimensions(),
+      actual.dimensions()
+    )));
+  }
+
+  let (changed, diff) = compare(&expected, actual);
+  let changed_fraction = changed as f32 / (actual.width() * actual.height()) as f32;
+  if changed_fraction <= MAX_CHANGED {
+    return Ok(None);
+  }
+
+  let diff_path = Path::new(OUTPUT_DIR).join(format!("{}.diff.png", name));
+  screenshot::save_png(actual, &actual_path)?;
+  screenshot::save_png(&diff, &diff_path)?;
+  Ok(Some(format!(
+    "{}: {} pixels ({:.2}%) changed, see {} and {}",
+    name,
+    changed,
+    changed_fraction * 100.,
+    actual_path.display(),
+    diff_path.display()
+  )))
+}
+
+// Owns the GL context and everything the reference scenes are built from
+struct Harness {
+  _context: HeadlessContext,
+  gl: Context,
+  target: Framebuffer,
+  camera_ubo: UniformBlock<CameraBlock>,
+  lit_shader: Shader,
+  textured_shader: Shader,
+  text_shader: Shader,
+  screen_shader: Shader,
+  screen_geom: Mesh,
+  cube: Mesh,
+  plane: Mesh,
+  fonts: HashMap<String, Font>,
+}
+
+impl Harness {
+  async unsafe fn load() -> Result<Self> {
+    let (context, gl) = HeadlessContext::build(WIDTH, HEIGHT)?;
+    gl.viewport(0, 0, WIDTH as i32, HEIGHT as i32);
+    gl.enable(glow::DEPTH_TEST);
+    gl.enable(glow::CULL_FACE);
+    gl.enable(glow::BLEND);
+    gl.depth_func(glow::LEQUAL);
+    gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+
+    let (
+      lit_shader,
+      textured_shader,
+      text_shader,
+      screen_shader,
+      marble_texture,
+      metal_texture,
+      font,
+    ) = try_join!(
+      Shader::load(
+        &gl,
+        "tests/golden/shaders/lit.vert",
+        "assets/shaders/colors.frag",
+        None
+      ),
+      Shader::load(
+        &gl,
+        "tests/golden/shaders/lit.vert",
+        "tests/golden/shaders/textured.frag",
+        None
+      ),
+      Shader::load(
+        &gl,
+        "assets/shaders/text.vert",
+        "assets/shaders/text.frag",
+        None
+      ),
+      Shader::load(
+        &gl,
+        "assets/shaders/screen.vert",
+        "assets/shaders/screen.frag",
+        None
+      ),
+      TextureBuilder::new(&gl).load("assets/textures/marble.jpg"),
+      TextureBuilder::new(&gl).load("assets/textures/metal.png"),
+      Font::load(&gl, "assets/fonts/DejaVuSans.ttf")
+    )?;
+
+    let cube = Geometry::Cube {
+      length: 1.,
+      width: 1.,
+      height: 1.,
+    }
+    .to_mesh(
+      &gl,
+      Some(Material {
+        diffuse: marble_texture.clone(),
+        specular: marble_texture,
+        shininess: 16.,
+        reflectivity: 0.,
+      }),
+    )?;
+    let plane = Geometry::Plane {
+      length: 4.,
+      width: 4.,
+      normal: glm::vec3(0., 1., 0.),
+    }
+    .to_mesh(
+      &gl,
+      Some(Material {
+        diffuse: metal_texture.clone(),
+        specular: metal_texture,
+        shininess: 16.,
+        reflectivity: 0.,
+      }),
+    )?;
+    let screen_geom = Geometry::Plane {
+      length: 2.,
+      width: 2.,
+      normal: glm::zero(),
+    }
+    .to_mesh(&gl, None)?;
+
+    let camera = Camera::new(
+      glm::vec3(1.5, 1.5, 3.),
+      glm::perspective(
+        WIDTH as f32 / HEIGHT as f32,
+        (45f32).to_radians(),
+        0.1,
+        100.,
+      ),
+      glm::zero(),
+    );
+    let camera_ubo = UniformBlock::new(&gl, 0)?;
+    camera_ubo.upload(&gl, &camera.uniform_block());
+    for shader in &[&lit_shader, &textured_shader] {
+      shader
+        .activate(&gl)
+        .bind_uniform(&gl, "CameraBlock", &camera_ubo);
+    }
+
+    Ok(Harness {
+      target: Framebuffer::new(&gl, WIDTH, HEIGHT, true)?,
+      _context: context,
+      gl,
+      camera_ubo,
+      lit_shader,
+      textured_shader,
+      text_shader,
+      screen_shader,
+      screen_geom,
+      cube,
+      plane,
+      fonts: hashmap! { font.name.clone() => font },
+    })
+  }
+
+  // Clear fbo, run draw into it and read back the target
+  unsafe fn render(&mut self, draw: impl FnOnce(&mut Self)) -> RgbaImage {
+    let fbo = self.target.fbo;
+    self.gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+    self.gl.clear_color(0.1, 0.1, 0.1, 1.);
+    self
+      .gl
+      .clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT | glow::STENCIL_BUFFER_BIT);
+    draw(self);
+    screenshot::read_framebuffer(&self.gl, Some(fbo), WIDTH, HEIGHT)
+  }
+
+  unsafe fn draw_lit(&self, mesh: &Mesh, model: &Mat4) {
+    let gl = &self.gl;
+    let sun = DirLight {
+      direction: glm::vec3(-1., -1., -1.),
+      ambient: glm::vec3(0.2, 0.2, 0.2),
+      diffuse: glm::vec3(0.8, 0.8, 0.8),
+      specular: glm::vec3(1., 1., 1.),
+    };
+    let mut shader = self.lit_shader.activate(gl);
+    shader.bind_uniform(gl, "dir_lights", &vec![sun]);
+    shader.bind_uniform(gl, "spot_lights", &Vec::<SpotLight>::new());
+    shader.bind_uniform(gl, "point_lights", &Vec::<PointLight>::new());
+    shader.bind_uniform(gl, "model", model);
+    mesh.draw(gl, &mut shader);
+  }
+
+  unsafe fn draw_lit_scene(&self) {
+    let cube = glm::rotation(30f32.to_radians(), &glm::vec3(0., 1., 0.));
+    self.draw_lit(&self.cube, &cube);
+    self.draw_lit(&self.plane, &glm::translation(&glm::vec3(0., -0.5, 0.)));
+  }
+
+  unsafe fn draw_text(&mut self, text: &str) {
+    let text = Text::new(
+      text,
+      "DejaVuSans",
+      24.,
+      [1., 1., 1., 1.],
+      glm::vec2(8., 50.),
+    );
+    text.draw(&mut self.fonts);
+    for font in self.fonts.values_mut() {
+      font
+        .draw(&self.gl, &self.text_shader, WIDTH, HEIGHT)
+        .unwrap();
+    }
+  }
+}
+
+#[test]
+fn golden_images() {
+  let runtime = tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()
+    .unwrap();
+  let mut failures = vec![];
+  let mut check = |name: &str, image: RgbaImage| match check_golden(name, &image) {
+    Ok(None) => {}
+    Ok(Some(failure)) => failures.push(failure),
+    Err(err) => failures.push(format!("{}: {:?}", name, err)),
+  };
+
+  unsafe {
+    let mut harness = runtime.block_on(Harness::load()).unwrap();
+
+    check(
+      "lit_cube",
+      harness.render(|harness| {
+        let model = glm::rotation(30f32.to_radians(), &glm::vec3(0., 1., 0.));
+        harness.draw_lit(&harness.cube, &model);
+      }),
+    );
+
+    check(
+      "textured_plane",
+      harness.render(|harness| {
+        let gl = &harness.gl;
+        let mut shader = harness.textured_shader.activate(gl);
+        shader.bind_uniform(gl, "model", &Mat4::identity());
+        harness.plane.draw(gl, &mut shader);
+      }),
+    );
+
+    check(
+      "text",
+      harness.render(|harness| harness.draw_text("Golden 123")),
+    );
+
+    // Each post pass runs alone over the same lit scene, then screen.frag
+    // copies the result out. With no pass enabled that's just screen.frag.
+    let gl = &harness.gl;
+    let scene = Framebuffer::new(gl, WIDTH, HEIGHT, true).unwrap();
+    let velocity = Framebuffer::new(gl, WIDTH, HEIGHT, false).unwrap();
+    // Every pixel moves by the same known amount, both in total (rg) and for
+    // the object alone (ba), so motion blur has something to smear. The
+    // target is 8-bit, so the motion is positive and in steps of 1/255.
+    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(velocity.fbo));
+    gl.clear_color(0.05, 0.02, 0.05, 0.02);
+    gl.clear(glow::COLOR_BUFFER_BIT);
+    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(scene.fbo));
+    gl.clear_color(0.1, 0.1, 0.1, 1.);
+    gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT | glow::STENCIL_BUFFER_BIT);
+    harness.draw_lit_scene();
+    // The lit scene is too dark for a subtle pass like motion blur to show
+    // up in the diff, so give every pass some hard white edges as well.
+    harness.draw_text("Golden 123");
+    let gl = &harness.gl;
+
+    let mut post = runtime
+      .block_on(PostStack::load(gl, "assets/post.cfg", WIDTH, HEIGHT))
+      .unwrap();
+    let names = iter::once(None)
+      .chain(post.passes().iter().map(|pass| Some(pass.name.clone())))
+      .collect::<Vec<_>>();
+    for name in &names {
+      for other in names.iter().flatten() {
+        post
+          .set_enabled(other, Some(other) == name.as_ref())
+          .unwrap();
+      }
+
+      let image = harness.render(|harness| {
+        let gl = &harness.gl;
+        gl.disable(glow::DEPTH_TEST);
+        let output = post.apply(
+          gl,
+          &harness.screen_geom,
+          &scene.render_texture,
+          scene.depth_texture.as_ref().unwrap(),
+          &velocity.render_texture,
+        );
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(harness.target.fbo));
+        let mut shader = harness.screen_shader.activate(gl);
+        shader.bind_uniform(gl, "screenTexture", output);
+        harness.screen_geom.draw(gl, &mut shader);
+        gl.enable(glow::DEPTH_TEST);
+      });
+      check(
+        &format!("post_{}", name.as_deref().unwrap_or("none")),
+        image,
+      );
+    }
+  }
+
+  if !failures.is_empty() {
+    panic!(
+      "{} golden image(s) differ:\n{}",
+      failures.len(),
+      failures.join("\n")
+    );
+  }
+}
@@ -0,0 +1,191 @@
+use crate::{camera::Camera, prelude::*, renderer::Renderer, scene::Scene, screenshot};
+use glutin::{dpi::PhysicalSize, event_loop::EventLoop, ContextBuilder, PossiblyCurrent};
+
+// Options for rendering without a window, parsed from the command line:
+//   --headless [--frames N] [--dt SECONDS] [--size WxH] [--camera X,Y,Z]
+//              [--look-at X,Y,Z] [--output PATH]
+pub struct HeadlessOptions {
+  pub frames: u32,
+  pub dt: f32,
+  pub width: u32,
+  pub height: u32,
+  pub camera: Vec3,
+  pub look_at: Vec3,
+  pub output: String,
+}
+
+impl Default for HeadlessOptions {
+  fn default() -> Self {
+    HeadlessOptions {
+      frames: 1,
+      dt: 1. / 60.,
+      width: 1024,
+      height: 768,
+      camera: glm::vec3(0.5, 1.5, 5.),
+      look_at: glm::zero(),
+      output: "render.png".to_string(),
+    }
+  }
+}
+
+fn parse_vec3(s: &str) -> Result<Vec3> {
+  let parts = s
+    .split(',')
+    .map(|part| part.trim().parse::<f32>())
+    .collect::<Result<Vec<_>, _>>()?;
+  if parts.len() != 3 {
+    bail!("Expected x,y,z but got {}", s);
+  }
+  Ok(glm::vec3(parts[0], parts[1], parts[2]))
+}
+
+impl HeadlessOptions {
+  pub fn parse(args: &[String]) -> Result<Self> {
+    let mut options = HeadlessOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+      if arg == "--headless" {
+        continue;
+      }
+
+      let value = args
+        .next()
+        .with_context(|| format!("Missing value for {}", arg))?;
+      let context = || format!("Invalid value for {}: {}", arg, value);
+      match arg.as_str() {
+        "--frames" => options.frames = value.parse().with_context(context)?,
+        "--dt" => options.dt = value.parse().with_context(context)?,
+        "--size" => {
+          let (width, height) = value.split_at(value.find('x').with_context(context)?);
+          options.width = width.parse().with_context(context)?;
+          options.height = height[1..].parse().with_context(context)?;
+        }
+        "--camera" => options.camera = parse_vec3(value).with_context(context)?,
+        "--look-at" => options.look_at = parse_vec3(value).with_context(context)?,
+        "--output" => options.output = value.clone(),
+        _ => bail!("Unknown argument {}", arg),
+      }
+    }
+
+    if options.frames == 0 {
+      bail!("--frames must be at least 1");
+    }
+    Ok(options)
+  }
+}
+
+// An OpenGL context with no window attached, drawn into through framebuffers
+pub struct HeadlessContext {
+  context: glutin::Context<PossiblyCurrent>,
+
+  // Surfaceless and pbuffer contexts need the display connection kept open
+  _event_loop: Option<EventLoop<()>>,
+}
+
+impl HeadlessContext {
+  pub unsafe fn build(width: u32, height: u32) -> Result<(Self, Context)> {
+    let builder = || {
+      ContextBuilder::new()
+        .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (3, 3)))
+        .with_gl_profile(glutin::GlProfile::Core)
+    };
+    let size = PhysicalSize::new(width, height);
+
+    // OSMesa renders in software without any display server, so try it first
+    #[cfg(target_os = "linux")]
+    let osmesa_error = {
+      use glutin::platform::unix::HeadlessContextExt;
+      match builder().build_osmesa(size) {
+        Ok(context) => return Self::make_current(context, None),
+        Err(err) => err,
+      }
+    };
+
+    let result = Self::build_with_display(builder, size);
+    #[cfg(target_os = "linux")]
+    let result = result.with_context(|| format!("OSMesa unavailable: {}", osmesa_error));
+    result.context("Failed to create a headless OpenGL context")
+  }
+
+  // Asks the display for a surfaceless context, then a pbuffer
+  unsafe fn build_with_display(
+    builder: impl Fn() -> ContextBuilder<'static, glutin::NotCurrent>,
+    size: PhysicalSize<u32>,
+  ) -> Result<(Self, Context)> {
+    // Creating the event loop panics if there's no display to connect to
+    #[cfg(target_os = "linux")]
+    {
+      if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+        bail!("No X11 or Wayland display (neither DISPLAY nor WAYLAND_DISPLAY is set)");
+      }
+    }
+
+    // Test threads aren't the main thread, which winit normally insists on
+    #[cfg(target_os = "linux")]
+    let event_loop = {
+      use glutin::platform::unix::EventLoopExtUnix;
+      EventLoop::new_any_thread()
+    };
+    #[cfg(not(target_os = "linux"))]
+    let event_loop = EventLoop::new();
+    #[cfg(target_os = "linux")]
+    {
+      use glutin::platform::unix::HeadlessContextExt;
+      if let Ok(context) = builder().build_surfaceless(&event_loop) {
+        return Self::make_current(context, Some(event_loop));
+      }
+    }
+    let context = builder().build_headless(&event_loop, size)?;
+    Self::make_current(context, Some(event_loop))
+  }
+
+  unsafe fn make_current(
+    context: glutin::Context<glutin::NotCurrent>,
+    event_loop: Option<EventLoop<()>>,
+  ) -> Result<(Self, Context)> {
+    let context = context.make_current().map_err(|(_, err)| err)?;
+    let gl = glow::Context::from_loader_function(|s| context.get_proc_address(s) as *const _);
+    Ok((
+      HeadlessContext {
+        context,
+        _event_loop: event_loop,
+      },
+      gl,
+    ))
+  }
+}
+
+// Render options.frames frames at a fixed timestep and write the last one out
+pub async fn run(options: HeadlessOptions) -> Result<()> {
+  unsafe {
+    let (width, height) = (options.width, options.height);
+    let (_context, gl) = HeadlessContext::build(width, height)?;
+    gl.viewport(0, 0, width as i32, height as i32);
+
+    let mut scene = Scene::build(&gl).await?;
+    let mut camera = Camera::new(
+      options.camera,
+      glm::perspective(
+        width as f32 / height as f32,
+        (45f32).to_radians(),
+        0.1,
+        100.,
+      ),
+      options.look_at,
+    );
+    let mut renderer = Renderer::load(&gl, &scene, width, height, true).await?;
+
+    // Earlier frames fill TAA history and advance animations
+    for frame in 0..options.frames {
+      let time = frame as f32 * options.dt;
+      scene.update(time, options.dt, &camera);
+      renderer.draw(&gl, &mut scene, &mut camera, time)?;
+    }
+    gl.finish();
+
+    let image = screenshot::read_framebuffer(&gl, renderer.output_fbo(), width, height);
+    screenshot::save_png(&image, &options.output)?;
+  }
+
+  Ok(())
+}
@@ -0,0 +1,50 @@
+use crate::{prelude::*, shader};
+use instant::Instant;
+
+// Seconds between checks of the shader files
+const POLL_INTERVAL: f32 = 0.5;
+
+// Rebuilds shaders when their files are saved. Modification times are
+// polled, which is cheap enough for the few dozen files shaders come from.
+pub struct ShaderWatcher {
+  last_poll: Instant,
+
+  // Build errors of shaders still running their old program
+  errors: Option<String>,
+}
+
+impl Default for ShaderWatcher {
+  fn default() -> Self {
+    ShaderWatcher {
+      last_poll: Instant::now(),
+      errors: None,
+    }
+  }
+}
+
+impl ShaderWatcher {
+  // Call between frames, not while a shader is active. Rebuilding blocks
+  // until every changed shader is compiled and linked, so the frame after a
+  // save stalls for that long. Compiling has to happen on this thread with
+  // the context current anyway, and frames without changes only pay for
+  // checking modification times.
+  pub unsafe fn poll(&mut self, gl: &Context) {
+    if self.last_poll.elapsed().as_secs_f32() < POLL_INTERVAL {
+      return;
+    }
+    self.last_poll = Instant::now();
+
+    // Tokio's file reads run on its blocking pool, so waiting on them here
+    // doesn't stall the runtime they need
+    let errors = futures::executor::block_on(shader::reload_changed(gl));
+    self.errors = if errors.is_empty() {
+      None
+    } else {
+      Some(errors.join("\n\n"))
+    };
+  }
+
+  pub fn errors(&self) -> Option<&str> {
+    self.errors.as_deref()
+  }
+}
@@ -1,6 +1,6 @@
 use crate::prelude::*;
 
-#[derive(BindUniform, ShaderTypeDef)]
+#[derive(BindUniform, ShaderTypeDef, Clone)]
 pub struct DirLight {
   pub direction: Vec3,
 
@@ -22,7 +22,7 @@ pub struct PointLight {
   pub quadratic: f32,
 }
 
-#[derive(BindUniform, ShaderTypeDef)]
+#[derive(BindUniform, ShaderTypeDef, Clone)]
 pub struct SpotLight {
   pub position: Vec3,
   pub direction: Vec3,
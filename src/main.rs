@@ -2,8 +2,13 @@
 #![allow(dead_code)]
 
 use crate::{camera::Camera, prelude::*, scene::Scene, user_inputs::UserInputs, window::Window};
+#[cfg(not(target_arch = "wasm32"))]
+use hot_reload::ShaderWatcher;
 use instant::Instant;
-use screen_capture::ScreenCapture;
+use post_process::{FxaaPreset, ParamValue};
+use renderer::Renderer;
+#[cfg(not(target_arch = "wasm32"))]
+use screenshot::{RecordFormat, Recorder};
 #[cfg(target_arch = "wasm32")]
 use winit::event::{ElementState, MouseButton};
 use winit::{
@@ -13,27 +18,74 @@ use winit::{
   window::WindowBuilder,
 };
 
+mod animation;
+mod billboard;
 mod camera;
+mod debug_draw;
 mod geometry;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod golden_tests;
+#[cfg(not(target_arch = "wasm32"))]
+mod headless;
+#[cfg(not(target_arch = "wasm32"))]
+mod hot_reload;
 mod io;
 mod light;
 mod material;
 mod mesh;
 mod model;
+mod particles;
+mod post_process;
 mod prelude;
+mod preprocessor;
+mod profiler;
+mod reflection;
+mod render_mode;
+mod renderer;
 mod scene;
 mod screen_capture;
+mod screenshot;
 mod shader;
+mod simplify;
+mod sky;
+mod ssr;
+mod stats;
+mod taa;
+mod terrain;
 mod text;
 mod texture;
 mod user_inputs;
+mod volumetrics;
+mod water;
 mod window;
 
 struct State {
   scene: Scene,
   camera: Camera,
   user_inputs: UserInputs,
-  shader_effect: i32,
+  renderer: Renderer,
+
+  // 0 shows the plain scene, otherwise only unpinned post pass (post_effect - 1) runs
+  post_effect: usize,
+  fxaa: Option<FxaaPreset>,
+
+  // Frustum of the camera when it was frozen for debug drawing
+  debug_frustum: Option<Mat4>,
+
+  // Set by the screenshot key and taken once the next frame is drawn
+  #[cfg(not(target_arch = "wasm32"))]
+  screenshot_requested: bool,
+
+  // While recording, every drawn frame is captured and time runs at a fixed step
+  #[cfg(not(target_arch = "wasm32"))]
+  recorder: Option<Recorder>,
+
+  // Rebuilds shaders as their files are edited
+  #[cfg(not(target_arch = "wasm32"))]
+  shader_watcher: ShaderWatcher,
+
+  // Last status message, e.g. where a screenshot went, and when it was set
+  notice: Option<(String, Instant)>,
 
   start: Instant,
   last_tick: Instant,
@@ -41,12 +93,55 @@ struct State {
 
 impl State {
   pub fn elapsed(&self) -> f32 {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(recorder) = self.recorder.as_ref() {
+      return recorder.time();
+    }
     self.start.elapsed().as_nanos() as f32 / 1e9
   }
 
-  pub fn dt(&self) -> f32 {
+  // Shows a message on screen for a few seconds
+  pub fn notify(&mut self, notice: String) {
+    self.notice = Some((notice, Instant::now()));
+  }
+
+  // Time since the last update, consumed by the update
+  pub fn dt(&mut self) -> f32 {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(recorder) = self.recorder.as_mut() {
+      return recorder.take_dt();
+    }
     self.last_tick.elapsed().as_nanos() as f32 / 1e9
   }
+
+  #[cfg(not(target_arch = "wasm32"))]
+  fn toggle_recording(&mut self, format: RecordFormat) -> anyhow::Result<()> {
+    match self.recorder.take() {
+      Some(recorder) => {
+        // Carry on from the simulated time rather than jumping to the wall clock
+        self.start = Instant::now() - std::time::Duration::from_secs_f32(recorder.time());
+        let notice = format!(
+          "Recorded {} frames to {}",
+          recorder.frames(),
+          recorder.path().display()
+        );
+        recorder.finish()?;
+        self.notify(notice);
+      }
+      None => {
+        let path = match format {
+          RecordFormat::Png => screenshot::next_path("recordings", "recording", ""),
+          RecordFormat::Y4m => screenshot::next_path("recordings", "recording", ".y4m"),
+        };
+        let (width, height) = self.renderer.size();
+        let start_time = self.elapsed();
+        self.recorder = Some(Recorder::start(
+          path, format, width, height, RECORD_FPS, start_time,
+        )?);
+      }
+    }
+    Ok(())
+  }
 }
 
 fn lock_cursor(window: &Window) {
@@ -55,6 +150,9 @@ fn lock_cursor(window: &Window) {
 }
 
 const DRAW_RATE: f32 = 60.;
+const NOTICE_SECONDS: f32 = 3.;
+#[cfg(not(target_arch = "wasm32"))]
+const RECORD_FPS: u32 = 30;
 
 unsafe fn run_event_loop(
   gl: Context,
@@ -173,14 +271,14 @@ async fn run() -> anyhow::Result<()> {
       glm::zero(),
     );
 
-    // Turn on OpenGL features
-    gl.enable(glow::DEPTH_TEST);
-    gl.enable(glow::STENCIL_TEST);
-    gl.enable(glow::BLEND);
-    gl.enable(glow::CULL_FACE);
+    let renderer = Renderer::load(&gl, &scene, width, height, false).await?;
 
-    gl.depth_func(glow::LEQUAL);
-    gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+    // F5 starts from whatever post.cfg set FXAA to
+    let fxaa = renderer
+      .post
+      .pass("fxaa")
+      .filter(|pass| pass.enabled)
+      .and_then(|pass| pass.fxaa_preset());
 
     // Build monotlithic state object
     let state = State {
@@ -189,46 +287,203 @@ async fn run() -> anyhow::Result<()> {
       user_inputs: UserInputs::default(),
       start: Instant::now(),
       last_tick: Instant::now(),
-      shader_effect: 0,
+      renderer,
+      post_effect: 0,
+      fxaa,
+      debug_frustum: None,
+      #[cfg(not(target_arch = "wasm32"))]
+      screenshot_requested: false,
+      #[cfg(not(target_arch = "wasm32"))]
+      recorder: None,
+      #[cfg(not(target_arch = "wasm32"))]
+      shader_watcher: ShaderWatcher::default(),
+      notice: None,
     };
 
-    let screen_capture = ScreenCapture::new(&gl, width, height).await?;
-
     let draw = move |gl: &Context, state: &mut State| {
-      screen_capture.record(gl);
+      state.scene.set_overlay(state.renderer.overlay());
 
-      // Clear the screen with a default color
-      gl.clear_color(0.1, 0.1, 0.1, 1.0);
-      gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT | glow::STENCIL_BUFFER_BIT);
-      gl.enable(glow::DEPTH_TEST);
-
-      // Draw the scene
+      // Shader build errors stay on screen until the file is fixed
+      #[cfg(not(target_arch = "wasm32"))]
+      {
+        state.shader_watcher.poll(gl);
+        state.scene.set_shader_errors(state.shader_watcher.errors());
+      }
+      let notice = state
+        .notice
+        .as_ref()
+        .filter(|(_, shown)| shown.elapsed().as_secs_f32() < NOTICE_SECONDS)
+        .map(|(notice, _)| notice.as_str());
+      state.scene.set_notice(notice);
+
+      let time = state.elapsed();
       state
-        .scene
-        .draw(gl, &state.camera, state.elapsed(), width, height)
+        .renderer
+        .draw(gl, &mut state.scene, &mut state.camera, time)
         .unwrap();
 
-      screen_capture.replay(gl, |gl, shader| {
-        shader.bind_uniform(gl, "effect", &state.shader_effect);
-      });
+      #[cfg(not(target_arch = "wasm32"))]
+      {
+        let fbo = state.renderer.output_fbo();
+        if state.screenshot_requested {
+          state.screenshot_requested = false;
+          let (width, height) = state.renderer.size();
+          let image = screenshot::read_framebuffer(gl, fbo, width, height);
+          let path = screenshot::next_path("screenshots", "screenshot", ".png");
+          match screenshot::save_png(&image, &path) {
+            Ok(()) => state.notify(format!("Saved {}", path.display())),
+            Err(err) => state.notify(format!("{:#}", err)),
+          }
+        }
+        if let Some(recorder) = state.recorder.as_mut() {
+          if let Err(err) = recorder.capture(gl, fbo) {
+            state.notify(format!("Stopped recording: {:#}", err));
+            state.recorder = None;
+          }
+        }
+      }
     };
 
     let update = move |state: &mut State, event: Event<()>, cursor_locked| {
       if cursor_locked {
         state.user_inputs.update(&event);
       }
+      state.renderer.profiler.cpu_begin("update");
 
+      // Tab cycles through running each post pass on its own
       if state.user_inputs.just_pressed(Key::Tab) {
-        let num_effects = 6;
-        state.shader_effect = if state.user_inputs.pressed(Key::LShift) {
-          (state.shader_effect + num_effects - 1) % num_effects
+        let num_effects = state.renderer.post.num_unpinned() + 1;
+        state.post_effect = if state.user_inputs.pressed(Key::LShift) {
+          (state.post_effect + num_effects - 1) % num_effects
         } else {
-          (state.shader_effect + 1) % num_effects
+          (state.post_effect + 1) % num_effects
         };
+        state.renderer.post.solo(state.post_effect.checked_sub(1));
       }
 
-      state.camera.update(state.dt(), &state.user_inputs);
-      state.scene.update(state.elapsed(), &state.camera);
+      let dt = state.dt();
+      state.camera.update(dt, &state.user_inputs);
+
+      // F5 steps FXAA through its quality presets and off, F6 shows the edges it finds
+      if state.user_inputs.just_pressed(Key::F5) {
+        state.fxaa = match state.fxaa {
+          None => Some(FxaaPreset::Low),
+          Some(FxaaPreset::Extreme) => None,
+          Some(preset) => Some(preset.next()),
+        };
+        if let Some(fxaa) = state.renderer.post.pass_mut("fxaa") {
+          fxaa.enabled = state.fxaa.is_some();
+          fxaa.set_fxaa_preset(state.fxaa.unwrap_or_default());
+        }
+      }
+      if state.user_inputs.just_pressed(Key::F6) {
+        if let Some(fxaa) = state.renderer.post.pass_mut("fxaa") {
+          let show_edges = fxaa.param("show_edges") == Some(&ParamValue::Bool(true));
+          if let Err(err) = fxaa.set_param("show_edges", !show_edges) {
+            state.notify(format!("{:#}", err));
+          }
+        }
+      }
+
+      // F7 toggles temporal anti-aliasing
+      if state.user_inputs.just_pressed(Key::F7) {
+        state.renderer.taa.enabled = !state.renderer.taa.enabled;
+      }
+
+      // F8 toggles screen-space reflections
+      if state.user_inputs.just_pressed(Key::F8) {
+        state.renderer.ssr.enabled = !state.renderer.ssr.enabled;
+      }
+
+      // F4 cycles between the final image and grayscale/false color depth
+      if state.user_inputs.just_pressed(Key::F4) {
+        state.renderer.depth_view = state.renderer.depth_view.next();
+      }
+
+      // F9 saves a screenshot, F10 starts and stops recording a PNG sequence
+      // (LShift+F10 a Y4M video) into the working directory
+      #[cfg(not(target_arch = "wasm32"))]
+      {
+        if state.user_inputs.just_pressed(Key::F9) {
+          state.screenshot_requested = true;
+        }
+        if state.user_inputs.just_pressed(Key::F10) {
+          let format = if state.user_inputs.pressed(Key::LShift) {
+            RecordFormat::Y4m
+          } else {
+            RecordFormat::Png
+          };
+          if let Err(err) = state.toggle_recording(format) {
+            state.notify(format!("{:#}", err));
+          }
+        }
+      }
+
+      // F11 toggles the profiler overlay, F12 saves recent timings as a Chrome trace
+      if state.user_inputs.just_pressed(Key::F11) {
+        state.renderer.profiler.enabled = !state.renderer.profiler.enabled;
+      }
+      #[cfg(not(target_arch = "wasm32"))]
+      if state.user_inputs.just_pressed(Key::F12) {
+        let path = screenshot::next_path("traces", "trace", ".json");
+        match state.renderer.profiler.write_trace(&path) {
+          Ok(()) => state.notify(format!("Saved {}", path.display())),
+          Err(err) => state.notify(format!("{:#}", err)),
+        }
+      }
+
+      // Backtick toggles the FPS and render statistics overlay
+      if state.user_inputs.just_pressed(Key::Grave) {
+        state.renderer.stats.enabled = !state.renderer.stats.enabled;
+      }
+
+      // F3 cycles debug render modes
+      if state.user_inputs.just_pressed(Key::F3) {
+        let modes = &mut state.scene.render_modes;
+        modes.mode = modes.mode.cycle(state.user_inputs.pressed(Key::LShift));
+      }
+
+      // F1 toggles debug drawing, F2 freezes the current camera frustum
+      if state.user_inputs.just_pressed(Key::F1) {
+        state.scene.debug_draw.enabled = !state.scene.debug_draw.enabled;
+      }
+      if state.user_inputs.just_pressed(Key::F2) {
+        state.debug_frustum = match state.debug_frustum {
+          Some(_) => None,
+          None => Some(state.camera.view_projection()),
+        };
+      }
+
+      // G toggles god rays, shift+G toggles spot light fog
+      if state.user_inputs.just_pressed(Key::G) {
+        let volumetrics = &mut state.renderer.volumetrics;
+        if state.user_inputs.pressed(Key::LShift) {
+          volumetrics.spot_fog = !volumetrics.spot_fog;
+        } else {
+          volumetrics.god_rays = !volumetrics.god_rays;
+        }
+      }
+
+      // K switches between the cubemap and procedural sky, and holding [ or ]
+      // scrubs the procedural sky's time of day
+      if state.user_inputs.just_pressed(Key::K) {
+        state.scene.sky = state.scene.sky.toggle();
+      }
+      if state.user_inputs.pressed(Key::LBracket) {
+        state.scene.time_of_day.advance(-4. * dt);
+      }
+      if state.user_inputs.pressed(Key::RBracket) {
+        state.scene.time_of_day.advance(4. * dt);
+      }
+
+      state.scene.update(state.elapsed(), dt, &state.camera);
+      if let Some(frustum) = state.debug_frustum.as_ref() {
+        state
+          .scene
+          .debug_draw
+          .frustum(frustum, glm::vec4(1., 0., 1., 1.), true);
+      }
+      state.renderer.profiler.cpu_end();
       state.last_tick = Instant::now();
     };
 
@@ -240,6 +495,22 @@ async fn run() -> anyhow::Result<()> {
 
 fn main() {
   let future = async {
+    // Passing --headless renders to an image file instead of opening a window
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+      let args = std::env::args().skip(1).collect::<Vec<_>>();
+      if args.iter().any(|arg| arg == "--headless") {
+        let result = match headless::HeadlessOptions::parse(&args) {
+          Ok(options) => headless::run(options).await,
+          Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+          panic!("{:?}", err);
+        }
+        return;
+      }
+    }
+
     if let Err(err) = run().await {
       panic!("{:?}", err);
     }
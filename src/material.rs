@@ -5,4 +5,7 @@ pub struct Material {
   pub diffuse: Texture,
   pub specular: Texture,
   pub shininess: f32,
+
+  // How much screen-space reflections show on the surface, 0 to opt out
+  pub reflectivity: f32,
 }
@@ -1,4 +1,4 @@
-use crate::{material::Material, model::Model, prelude::*, shader::ActiveShader};
+use crate::{material::Material, model::Model, prelude::*, shader::ActiveShader, simplify, stats};
 use std::mem::size_of;
 
 #[derive(Debug, Clone)]
@@ -22,23 +22,67 @@ impl Vertex {
   }
 }
 
+// Per-vertex skinning data: up to 4 joints that influence the vertex, and their weights
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct VertexSkin {
+  pub joints: [i32; 4],
+  pub weights: Vec4,
+}
+
 #[derive(Clone)]
 pub struct Mesh {
   pub vertices: Vec<Vertex>,
   pub indices: Vec<u32>,
+  pub skin: Option<Vec<VertexSkin>>,
+
+  // Direction of increasing u per vertex, for shaders that need a tangent frame
+  pub tangents: Option<Vec<Vec3>>,
   pub material: Option<Material>,
 
   vao: GlVertexArray,
   vbo: GlBuffer,
   ebo: GlBuffer,
+  skin_vbo: Option<GlBuffer>,
+  tangent_vbo: Option<GlBuffer>,
 }
 
+// Attribute location of tangents, after the skinning attributes
+const TANGENT_LOCATION: u32 = 5;
+
 impl Mesh {
   pub unsafe fn new(
     gl: &Context,
     vertices: Vec<Vertex>,
     indices: Vec<u32>,
     material: Option<Material>,
+  ) -> Result<Mesh> {
+    Self::build(gl, vertices, indices, None, material)
+  }
+
+  pub unsafe fn new_skinned(
+    gl: &Context,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    skin: Vec<VertexSkin>,
+    material: Option<Material>,
+  ) -> Result<Mesh> {
+    if skin.len() != vertices.len() {
+      bail!(
+        "Mesh has {} vertices but {} skin entries",
+        vertices.len(),
+        skin.len()
+      );
+    }
+    Self::build(gl, vertices, indices, Some(skin), material)
+  }
+
+  unsafe fn build(
+    gl: &Context,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    skin: Option<Vec<VertexSkin>>,
+    material: Option<Material>,
   ) -> Result<Mesh> {
     // Vertex array
     let vao = gl.create_vertex_array().map_err(Error::msg)?;
@@ -77,22 +121,87 @@ impl Mesh {
       offset += size;
     }
 
+    // Skinning attributes live in a separate buffer after the regular attributes
+    let skin_vbo = match skin.as_ref() {
+      Some(skin) => {
+        let skin_vbo = gl.create_buffer().map_err(Error::msg)?;
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(skin_vbo));
+
+        let (_, skin_bytes, _) = skin.align_to::<u8>();
+        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, skin_bytes, glow::STATIC_DRAW);
+
+        let joints_index = sizes.len() as u32;
+        let stride = size_of::<VertexSkin>() as i32;
+        gl.enable_vertex_attrib_array(joints_index);
+        gl.vertex_attrib_pointer_i32(joints_index, 4, glow::INT, stride, 0);
+        gl.enable_vertex_attrib_array(joints_index + 1);
+        gl.vertex_attrib_pointer_f32(
+          joints_index + 1,
+          4,
+          glow::FLOAT,
+          false,
+          stride,
+          4 * size_of::<i32>() as i32,
+        );
+
+        Some(skin_vbo)
+      }
+      None => None,
+    };
+
     // Reset vertex array
     gl.bind_vertex_array(None);
 
     Ok(Mesh {
       vertices,
       indices,
+      skin,
+      tangents: None,
       material,
       vao,
       ebo,
       vbo,
+      skin_vbo,
+      tangent_vbo: None,
     })
   }
 
+  // Adds a per-vertex tangent attribute in its own buffer
+  pub unsafe fn with_tangents(mut self, gl: &Context, tangents: Vec<Vec3>) -> Result<Self> {
+    if tangents.len() != self.vertices.len() {
+      bail!(
+        "Mesh has {} vertices but {} tangents",
+        self.vertices.len(),
+        tangents.len()
+      );
+    }
+
+    let tangent_vbo = gl.create_buffer().map_err(Error::msg)?;
+    gl.bind_vertex_array(Some(self.vao));
+    gl.bind_buffer(glow::ARRAY_BUFFER, Some(tangent_vbo));
+
+    let (_, tangent_bytes, _) = tangents.align_to::<u8>();
+    gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, tangent_bytes, glow::STATIC_DRAW);
+
+    gl.enable_vertex_attrib_array(TANGENT_LOCATION);
+    gl.vertex_attrib_pointer_f32(
+      TANGENT_LOCATION,
+      3,
+      glow::FLOAT,
+      false,
+      size_of::<Vec3>() as i32,
+      0,
+    );
+    gl.bind_vertex_array(None);
+
+    self.tangents = Some(tangents);
+    self.tangent_vbo = Some(tangent_vbo);
+    Ok(self)
+  }
+
   pub unsafe fn draw(&self, gl: &Context, shader: &mut ActiveShader) {
     if let Some(material) = self.material.as_ref() {
-      shader.bind_uniform(gl, "material", material);
+      shader.bind_optional_uniform(gl, "material", material);
     }
 
     gl.bind_vertex_array(Some(self.vao));
@@ -103,11 +212,47 @@ impl Mesh {
       0,
     );
     gl.bind_vertex_array(None);
+    stats::record_draw(self.indices.len() as u64 / 3);
 
     shader.reset_textures();
   }
 
+  // Lower detail copy with about ratio as many triangles, sharing the material
+  pub unsafe fn simplified(&self, gl: &Context, ratio: f32) -> Result<Mesh> {
+    let target = ((self.indices.len() / 3) as f32 * ratio) as usize;
+    let indices = simplify::simplify(&self.vertices, &self.indices, target);
+
+    // Keep only the vertices (and skin and tangents) still referenced, in order of first use
+    let mut remap = vec![None; self.vertices.len()];
+    let mut vertices = vec![];
+    let mut skin = self.skin.as_ref().map(|_| vec![]);
+    let mut tangents = self.tangents.as_ref().map(|_| vec![]);
+    let indices = indices
+      .into_iter()
+      .map(|i| {
+        let i = i as usize;
+        *remap[i].get_or_insert_with(|| {
+          vertices.push(self.vertices[i].clone());
+          if let (Some(skin), Some(old_skin)) = (skin.as_mut(), self.skin.as_ref()) {
+            skin.push(old_skin[i].clone());
+          }
+          if let (Some(tangents), Some(old_tangents)) = (tangents.as_mut(), self.tangents.as_ref())
+          {
+            tangents.push(old_tangents[i]);
+          }
+          (vertices.len() - 1) as u32
+        })
+      })
+      .collect();
+
+    let mesh = Self::build(gl, vertices, indices, skin, self.material.clone())?;
+    match tangents {
+      Some(tangents) => mesh.with_tangents(gl, tangents),
+      None => Ok(mesh),
+    }
+  }
+
   pub fn to_model(self) -> Model {
-    Model { meshes: vec![self] }
+    Model::new(vec![self])
   }
 }
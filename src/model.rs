@@ -1,4 +1,5 @@
 use crate::{
+  camera::Camera,
   io,
   material::Material,
   mesh::{Mesh, Vertex},
@@ -10,9 +11,31 @@ use futures::future::try_join_all;
 use image::DynamicImage;
 use std::{cell::RefCell, collections::HashMap, io::BufReader, path::Path};
 
+// How far past a level's threshold the screen size has to go before the
+// level changes, so models near a threshold don't flicker between levels
+const LOD_HYSTERESIS: f32 = 0.15;
+
 #[derive(Clone)]
 pub struct Model {
   pub meshes: Vec<Mesh>,
+
+  // Coarser versions of meshes, in order of decreasing detail
+  pub lods: Vec<Lod>,
+
+  // Bounding sphere in model space, for estimating size on screen
+  pub center: Vec3,
+  pub radius: f32,
+
+  // 0 draws meshes, otherwise lods[lod - 1]
+  lod: usize,
+}
+
+#[derive(Clone)]
+pub struct Lod {
+  pub meshes: Vec<Mesh>,
+
+  // Used once the model covers less than this fraction of the screen height
+  pub max_screen_size: f32,
 }
 
 // fn obj_to_mesh(model: tobj::Model, mtl: tobj::Material, files: &HashMap<String, Vec<u8>>) -> Mesh {
@@ -20,6 +43,82 @@ pub struct Model {
 // }
 
 impl Model {
+  pub fn new(meshes: Vec<Mesh>) -> Self {
+    let positions = meshes
+      .iter()
+      .flat_map(|mesh| mesh.vertices.iter().map(|vertex| vertex.position));
+    let (min, max) = positions.fold(
+      (Vec3::repeat(f32::MAX), Vec3::repeat(f32::MIN)),
+      |(min, max), p| (glm::min2(&min, &p), glm::max2(&max, &p)),
+    );
+    let center = (min + max) / 2.;
+    let radius = meshes
+      .iter()
+      .flat_map(|mesh| mesh.vertices.iter())
+      .map(|vertex| glm::distance(&vertex.position, &center))
+      .fold(0., f32::max);
+
+    Model {
+      meshes,
+      lods: vec![],
+      center,
+      radius,
+      lod: 0,
+    }
+  }
+
+  // Generates a level for each (triangle ratio, max screen size) pair by
+  // simplifying the full detail meshes
+  pub unsafe fn with_lods(mut self, gl: &Context, levels: &[(f32, f32)]) -> Result<Self> {
+    for &(ratio, max_screen_size) in levels {
+      let meshes = self
+        .meshes
+        .iter()
+        .map(|mesh| mesh.simplified(gl, ratio))
+        .collect::<Result<Vec<_>>>()?;
+      self.lods.push(Lod {
+        meshes,
+        max_screen_size,
+      });
+    }
+    Ok(self)
+  }
+
+  // Diameter of the bounding sphere on screen as a fraction of the screen height
+  pub fn screen_size(&self, transform: &Mat4, camera: &Camera) -> f32 {
+    let center = (transform * glm::vec4(self.center.x, self.center.y, self.center.z, 1.)).xyz();
+    let scale = (0..3)
+      .map(|i| transform.fixed_slice::<na::U3, na::U1>(0, i).norm())
+      .fold(0., f32::max);
+    let distance = glm::distance(&center, &camera.pos).max(1e-4);
+    self.radius * scale * camera.projection[(1, 1)] / distance
+  }
+
+  // Picks the level of detail to draw with for the given placement
+  pub fn update_lod(&mut self, transform: &Mat4, camera: &Camera) {
+    let size = self.screen_size(transform, camera);
+    while self.lod < self.lods.len()
+      && size < self.lods[self.lod].max_screen_size * (1. - LOD_HYSTERESIS)
+    {
+      self.lod += 1;
+    }
+    while self.lod > 0 && size > self.lods[self.lod - 1].max_screen_size * (1. + LOD_HYSTERESIS) {
+      self.lod -= 1;
+    }
+  }
+
+  pub fn lod(&self) -> usize {
+    self.lod
+  }
+
+  // Meshes of the current level of detail
+  pub fn active_meshes(&self) -> &[Mesh] {
+    match self.lod {
+      0 => &self.meshes,
+      lod => &self.lods[lod - 1].meshes,
+    }
+  }
+
   pub async unsafe fn load(gl: &Context, obj_dir: impl AsRef<Path>) -> Result<Model> {
     // Get a listing of all files in the model directory
     // We have to use a special dir.txt because you can't list directories on the web
@@ -92,6 +191,7 @@ impl Model {
           diffuse: load_texture(&obj_material.diffuse_texture)?,
           specular: load_texture(&obj_material.specular_texture)?,
           shininess: obj_material.shininess,
+          reflectivity: 0.,
         })
       })
       .collect::<Result<Vec<_>>>()?;
@@ -125,11 +225,11 @@ impl Model {
       })
       .collect::<Result<Vec<_>>>()?;
 
-    Ok(Model { meshes })
+    Ok(Model::new(meshes))
   }
 
   pub unsafe fn draw(&self, gl: &Context, shader: &mut ActiveShader) {
-    for mesh in &self.meshes {
+    for mesh in self.active_meshes() {
       mesh.draw(gl, shader);
     }
   }
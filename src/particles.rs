@@ -0,0 +1,491 @@
+use std::mem::size_of;
+
+use crate::{
+  prelude::*,
+  shader::{ActiveShader, BindUniform, Shader},
+  stats,
+  texture::Texture,
+};
+
+// Must match MAX_CURVE_KEYS in particle.vert
+const MAX_CURVE_KEYS: usize = 8;
+
+// A piecewise-linear curve over a particle's normalized age (0 = born, 1 = dead).
+// Curves are evaluated in the vertex shader so CPU and GPU simulation share a renderer.
+#[derive(Clone)]
+pub struct Curve<T> {
+  keys: Vec<(f32, T)>,
+}
+
+impl<T> Curve<T> {
+  pub fn new(mut keys: Vec<(f32, T)>) -> Result<Self> {
+    if keys.is_empty() || keys.len() > MAX_CURVE_KEYS {
+      bail!(
+        "Curves need 1 to {} keys, got {}",
+        MAX_CURVE_KEYS,
+        keys.len()
+      );
+    }
+    if keys.iter().any(|(time, _)| time.is_nan()) {
+      bail!("Curve key times can't be NaN");
+    }
+
+    // None of the times are NaN, so they're all comparable
+    keys.sort_by(|(t1, _), (t2, _)| t1.partial_cmp(t2).unwrap());
+    Ok(Curve { keys })
+  }
+
+  pub fn constant(value: T) -> Self {
+    Curve {
+      keys: vec![(0., value)],
+    }
+  }
+
+  pub fn linear(start: T, end: T) -> Self {
+    Curve {
+      keys: vec![(0., start), (1., end)],
+    }
+  }
+}
+
+impl<T: BindUniform> BindUniform for Curve<T> {
+  unsafe fn bind_uniform(&self, gl: &Context, shader: &mut ActiveShader, name: &str) {
+    shader.bind_uniform(gl, &format!("{}_len", name), &(self.keys.len() as i32));
+    for (i, (time, value)) in self.keys.iter().enumerate() {
+      shader.bind_uniform(gl, &format!("{}_times[{}]", name, i), time);
+      shader.bind_uniform(gl, &format!("{}_values[{}]", name, i), value);
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct EmitterConfig {
+  // Particles spawned per second
+  pub rate: f32,
+  pub max_particles: usize,
+
+  // Ranges are sampled uniformly per particle
+  pub lifetime: (f32, f32),
+  pub speed: (f32, f32),
+
+  // Particles leave in a cone around direction with the given half-angle (radians)
+  pub direction: Vec3,
+  pub spread: f32,
+  pub gravity: Vec3,
+
+  pub color: Curve<Vec4>,
+  pub size: Curve<f32>,
+  pub texture: Option<Texture>,
+  pub additive: bool,
+}
+
+impl Default for EmitterConfig {
+  fn default() -> Self {
+    EmitterConfig {
+      rate: 20.,
+      max_particles: 256,
+      lifetime: (1., 2.),
+      speed: (0.5, 1.),
+      direction: glm::vec3(0., 1., 0.),
+      spread: 0.3,
+      gravity: glm::zero(),
+      color: Curve::linear(glm::vec4(1., 1., 1., 1.), glm::vec4(1., 1., 1., 0.)),
+      size: Curve::constant(0.1),
+      texture: None,
+      additive: false,
+    }
+  }
+}
+
+// Per-particle state, shared by the CPU instance buffer and the GPU feedback buffers
+#[derive(Debug, Clone)]
+#[repr(C)]
+struct Particle {
+  position: Vec3,
+  velocity: Vec3,
+  age: f32,
+  lifetime: f32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Simulation {
+  Cpu,
+  Gpu,
+}
+
+enum SimState {
+  Cpu {
+    particles: Vec<Particle>,
+    vao: GlVertexArray,
+    vbo: GlBuffer,
+  },
+
+  // Two buffers are ping-ponged: one is read as vertex input while the other
+  // captures the transform feedback output
+  Gpu {
+    buffers: [GlBuffer; 2],
+    update_vaos: [GlVertexArray; 2],
+    render_vaos: [GlVertexArray; 2],
+    current: usize,
+    pending_dt: f32,
+    pending_spawn: usize,
+    spawn_start: usize,
+  },
+}
+
+// Small xorshift generator so emitters don't need a rand dependency
+struct Rng(u32);
+
+impl Rng {
+  fn next(&mut self) -> f32 {
+    self.0 ^= self.0 << 13;
+    self.0 ^= self.0 >> 17;
+    self.0 ^= self.0 << 5;
+    (self.0 as f32) / (u32::MAX as f32)
+  }
+
+  fn range(&mut self, (min, max): (f32, f32)) -> f32 {
+    min + (max - min) * self.next()
+  }
+}
+
+// Picks a random unit vector within `spread` radians of `axis`
+fn cone_direction(axis: &Vec3, spread: f32, r1: f32, r2: f32) -> Vec3 {
+  let axis = glm::normalize(axis);
+  let cos_theta = 1. + (spread.cos() - 1.) * r1;
+  let sin_theta = (1. - cos_theta * cos_theta).max(0.).sqrt();
+  let phi = r2 * std::f32::consts::PI * 2.;
+  let up = if axis.y.abs() < 0.999 {
+    glm::vec3(0., 1., 0.)
+  } else {
+    glm::vec3(1., 0., 0.)
+  };
+  let tangent = glm::normalize(&glm::cross(&up, &axis));
+  let bitangent = glm::cross(&axis, &tangent);
+  glm::normalize(
+    &(tangent * phi.cos() * sin_theta + bitangent * phi.sin() * sin_theta + axis * cos_theta),
+  )
+}
+
+// Lays out the Particle struct as vertex attributes 0-3
+unsafe fn particle_attributes(gl: &Context, divisor: u32) {
+  let size_f32 = size_of::<f32>() as i32;
+  let sizes = [3, 3, 1, 1];
+  let stride = sizes.iter().sum::<i32>() * size_f32;
+
+  let mut offset = 0;
+  for (i, size) in sizes.iter().enumerate() {
+    gl.enable_vertex_attrib_array(i as u32);
+    gl.vertex_attrib_pointer_f32(
+      i as u32,
+      *size,
+      glow::FLOAT,
+      false,
+      stride,
+      offset * size_f32,
+    );
+    gl.vertex_attrib_divisor(i as u32, divisor);
+    offset += size;
+  }
+}
+
+pub struct ParticleEmitter {
+  pub config: EmitterConfig,
+
+  // Position relative to the entity the emitter is attached to
+  pub offset: Vec3,
+
+  origin: Vec3,
+  spawn_accumulator: f32,
+  rng: Rng,
+  state: SimState,
+}
+
+impl ParticleEmitter {
+  pub unsafe fn new(
+    gl: &Context,
+    config: EmitterConfig,
+    offset: Vec3,
+    simulation: Simulation,
+  ) -> Result<Self> {
+    let state = match simulation {
+      Simulation::Cpu => {
+        let vao = gl.create_vertex_array().map_err(Error::msg)?;
+        gl.bind_vertex_array(Some(vao));
+        let vbo = gl.create_buffer().map_err(Error::msg)?;
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+        particle_attributes(gl, 1);
+        gl.bind_vertex_array(None);
+
+        SimState::Cpu {
+          particles: Vec::with_capacity(config.max_particles),
+          vao,
+          vbo,
+        }
+      }
+
+      Simulation::Gpu => {
+        // Zeroed particles have age == lifetime, so they start out dead
+        let dead = Particle {
+          position: glm::zero(),
+          velocity: glm::zero(),
+          age: 0.,
+          lifetime: 0.,
+        };
+        let initial = vec![dead; config.max_particles];
+        let (_, initial_bytes, _) = initial.align_to::<u8>();
+
+        let mut buffers = vec![];
+        let mut update_vaos = vec![];
+        let mut render_vaos = vec![];
+        for _ in 0..2 {
+          let buffer = gl.create_buffer().map_err(Error::msg)?;
+          gl.bind_buffer(glow::ARRAY_BUFFER, Some(buffer));
+          gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, initial_bytes, glow::DYNAMIC_COPY);
+
+          for (vaos, divisor) in vec![(&mut update_vaos, 0), (&mut render_vaos, 1)] {
+            let vao = gl.create_vertex_array().map_err(Error::msg)?;
+            gl.bind_vertex_array(Some(vao));
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(buffer));
+            particle_attributes(gl, divisor);
+            gl.bind_vertex_array(None);
+            vaos.push(vao);
+          }
+
+          buffers.push(buffer);
+        }
+        gl.bind_buffer(glow::ARRAY_BUFFER, None);
+
+        SimState::Gpu {
+          buffers: [buffers[0], buffers[1]],
+          update_vaos: [update_vaos[0], update_vaos[1]],
+          render_vaos: [render_vaos[0], render_vaos[1]],
+          current: 0,
+          pending_dt: 0.,
+          pending_spawn: 0,
+          spawn_start: 0,
+        }
+      }
+    };
+
+    Ok(ParticleEmitter {
+      config,
+      offset,
+      origin: offset,
+      spawn_accumulator: 0.,
+      rng: Rng(0x9E37_79B9),
+      state,
+    })
+  }
+
+  pub fn origin(&self) -> Vec3 {
+    self.origin
+  }
+
+  pub fn update(&mut self, dt: f32, parent: &Mat4, camera_pos: &Vec3) {
+    let ParticleEmitter {
+      config,
+      offset,
+      origin,
+      spawn_accumulator,
+      rng,
+      state,
+    } = self;
+
+    *origin = (parent * glm::vec4(offset.x, offset.y, offset.z, 1.)).xyz();
+
+    *spawn_accumulator += config.rate * dt;
+    let spawn_count = spawn_accumulator.floor();
+    *spawn_accumulator -= spawn_count;
+    let spawn_count = spawn_count as usize;
+
+    match state {
+      SimState::Cpu { particles, .. } => {
+        for particle in particles.iter_mut() {
+          particle.velocity += config.gravity * dt;
+          particle.position += particle.velocity * dt;
+          particle.age += dt;
+        }
+        particles.retain(|particle| particle.age < particle.lifetime);
+
+        let spawn_count = spawn_count.min(config.max_particles.saturating_sub(particles.len()));
+        for _ in 0..spawn_count {
+          let direction = cone_direction(&config.direction, config.spread, rng.next(), rng.next());
+          particles.push(Particle {
+            position: *origin,
+            velocity: direction * rng.range(config.speed),
+            age: 0.,
+            lifetime: rng.range(config.lifetime),
+          });
+        }
+
+        // Sort back-to-front so alpha blending within the emitter is correct
+        particles.sort_by_key(|particle| {
+          ordered_float::OrderedFloat(-glm::length2(&(camera_pos - particle.position)))
+        });
+      }
+
+      SimState::Gpu {
+        pending_dt,
+        pending_spawn,
+        ..
+      } => {
+        // The simulation step runs at draw time when we have a GL context
+        *pending_dt += dt;
+        *pending_spawn = (*pending_spawn + spawn_count).min(config.max_particles);
+      }
+    }
+  }
+
+  fn instance_count(&self) -> usize {
+    match &self.state {
+      SimState::Cpu { particles, .. } => particles.len(),
+      SimState::Gpu { .. } => self.config.max_particles,
+    }
+  }
+
+  fn render_vao(&self) -> GlVertexArray {
+    match &self.state {
+      SimState::Cpu { vao, .. } => *vao,
+      SimState::Gpu {
+        render_vaos,
+        current,
+        ..
+      } => render_vaos[*current],
+    }
+  }
+}
+
+// Shaders shared by every emitter in a scene
+pub struct ParticleRenderer {
+  shader: Shader,
+  update_shader: Shader,
+}
+
+impl ParticleRenderer {
+  pub async unsafe fn load(gl: &Context) -> Result<Self> {
+    let (shader, update_shader) = try_join!(
+      Shader::load(
+        gl,
+        "assets/shaders/particle.vert",
+        "assets/shaders/particle.frag",
+        None
+      ),
+      Shader::load_transform_feedback(
+        gl,
+        "assets/shaders/particle_update.vert",
+        &["out_position", "out_velocity", "out_age", "out_lifetime"]
+      )
+    )?;
+    Ok(ParticleRenderer {
+      shader,
+      update_shader,
+    })
+  }
+
+  pub fn shader(&self) -> &Shader {
+    &self.shader
+  }
+
+  // Upload CPU particles or advance the GPU simulation. Must be called once per frame
+  // for each emitter before drawing it.
+  pub unsafe fn prepare(&self, gl: &Context, emitter: &mut ParticleEmitter, time: f32) {
+    let ParticleEmitter {
+      config,
+      origin,
+      state,
+      ..
+    } = emitter;
+
+    match state {
+      SimState::Cpu { particles, vbo, .. } => {
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(*vbo));
+        let (_, particles_bytes, _) = particles.align_to::<u8>();
+        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, particles_bytes, glow::STREAM_DRAW);
+        gl.bind_buffer(glow::ARRAY_BUFFER, None);
+      }
+
+      SimState::Gpu {
+        buffers,
+        update_vaos,
+        current,
+        pending_dt,
+        pending_spawn,
+        spawn_start,
+        ..
+      } => {
+        if *pending_dt == 0. {
+          return;
+        }
+
+        let mut shader = self.update_shader.activate(gl);
+        shader.bind_uniform(gl, "dt", &*pending_dt);
+        shader.bind_uniform(gl, "time", &time);
+        shader.bind_uniform(gl, "origin", &*origin);
+        shader.bind_uniform(gl, "direction", &config.direction);
+        shader.bind_uniform(gl, "spread", &config.spread);
+        shader.bind_uniform(gl, "gravity", &config.gravity);
+        shader.bind_uniform(
+          gl,
+          "speed_range",
+          &glm::vec2(config.speed.0, config.speed.1),
+        );
+        shader.bind_uniform(
+          gl,
+          "lifetime_range",
+          &glm::vec2(config.lifetime.0, config.lifetime.1),
+        );
+        shader.bind_uniform(gl, "spawn_start", &(*spawn_start as i32));
+        shader.bind_uniform(gl, "spawn_count", &(*pending_spawn as i32));
+        shader.bind_uniform(gl, "max_particles", &(config.max_particles as i32));
+
+        // Run the update shader over every particle without rasterizing anything
+        gl.enable(glow::RASTERIZER_DISCARD);
+        gl.bind_vertex_array(Some(update_vaos[*current]));
+        gl.bind_buffer_base(
+          glow::TRANSFORM_FEEDBACK_BUFFER,
+          0,
+          Some(buffers[1 - *current]),
+        );
+        gl.begin_transform_feedback(glow::POINTS);
+        gl.draw_arrays(glow::POINTS, 0, config.max_particles as i32);
+        stats::record_draw(0);
+        gl.end_transform_feedback();
+        gl.bind_buffer_base(glow::TRANSFORM_FEEDBACK_BUFFER, 0, None);
+        gl.bind_vertex_array(None);
+        gl.disable(glow::RASTERIZER_DISCARD);
+
+        *current = 1 - *current;
+        *spawn_start = (*spawn_start + *pending_spawn) % config.max_particles;
+        *pending_dt = 0.;
+        *pending_spawn = 0;
+      }
+    }
+  }
+
+  pub unsafe fn draw(&self, gl: &Context, emitter: &ParticleEmitter) {
+    let config = &emitter.config;
+    let mut shader = self.shader.activate(gl);
+    shader.bind_uniform(gl, "color", &config.color);
+    shader.bind_uniform(gl, "size", &config.size);
+    shader.bind_uniform(gl, "has_texture", &config.texture.is_some());
+    if let Some(texture) = config.texture.as_ref() {
+      shader.bind_uniform(gl, "particle_tex", texture);
+    }
+
+    // Particles are blended but shouldn't occlude each other
+    gl.depth_mask(false);
+    if config.additive {
+      gl.blend_func(glow::SRC_ALPHA, glow::ONE);
+    }
+
+    // Each instance is a camera-facing quad generated from gl_VertexID
+    gl.bind_vertex_array(Some(emitter.render_vao()));
+    gl.draw_arrays_instanced(glow::TRIANGLE_STRIP, 0, 4, emitter.instance_count() as i32);
+    stats::record_draw(emitter.instance_count() as u64 * 2);
+    gl.bind_vertex_array(None);
+
+    gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+    gl.depth_mask(true);
+  }
+}
@@ -0,0 +1,615 @@
+use std::{collections::HashMap, mem};
+
+use crate::{
+  io,
+  mesh::Mesh,
+  prelude::*,
+  screen_capture::Framebuffer,
+  shader::{ActiveShader, BindUniform, Shader},
+  texture::Texture,
+};
+use futures::future::try_join_all;
+
+// Directory that pass shaders in a config file are relative to
+const SHADER_DIR: &str = "assets/shaders";
+
+// Must match MAX_KERNEL_LEN in convolve.frag
+const MAX_KERNEL_LEN: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParamValue {
+  Bool(bool),
+  Int(i32),
+  Float(f32),
+  Vec2(Vec2),
+  Vec3(Vec3),
+  Vec4(Vec4),
+  FloatArray(Vec<f32>),
+}
+
+impl ParamValue {
+  // Parses a config value: true/false, 3, 0.5, 1,0,0 (vec2-4) or [1,2,3] (float array)
+  pub fn parse(s: &str) -> Result<Self> {
+    let floats = |s: &str| -> Result<Vec<f32>> {
+      s.split(',')
+        .map(|f| f.trim().parse::<f32>().map_err(Error::msg))
+        .collect()
+    };
+
+    Ok(match s {
+      "true" => ParamValue::Bool(true),
+      "false" => ParamValue::Bool(false),
+      _ if s.starts_with('[') && s.ends_with(']') => {
+        ParamValue::FloatArray(floats(&s[1..s.len() - 1])?)
+      }
+      _ if s.contains(',') => {
+        let v = floats(s)?;
+        match v.len() {
+          2 => ParamValue::Vec2(glm::vec2(v[0], v[1])),
+          3 => ParamValue::Vec3(glm::vec3(v[0], v[1], v[2])),
+          4 => ParamValue::Vec4(glm::vec4(v[0], v[1], v[2], v[3])),
+          n => bail!("Vectors need 2-4 components, found {} in {:?}", n, s),
+        }
+      }
+      _ if s.contains('.') => ParamValue::Float(s.parse()?),
+      _ => ParamValue::Int(s.parse()?),
+    })
+  }
+}
+
+impl From<bool> for ParamValue {
+  fn from(value: bool) -> Self {
+    ParamValue::Bool(value)
+  }
+}
+
+impl From<i32> for ParamValue {
+  fn from(value: i32) -> Self {
+    ParamValue::Int(value)
+  }
+}
+
+impl From<f32> for ParamValue {
+  fn from(value: f32) -> Self {
+    ParamValue::Float(value)
+  }
+}
+
+impl From<Vec2> for ParamValue {
+  fn from(value: Vec2) -> Self {
+    ParamValue::Vec2(value)
+  }
+}
+
+impl From<Vec3> for ParamValue {
+  fn from(value: Vec3) -> Self {
+    ParamValue::Vec3(value)
+  }
+}
+
+impl From<Vec4> for ParamValue {
+  fn from(value: Vec4) -> Self {
+    ParamValue::Vec4(value)
+  }
+}
+
+impl From<Vec<f32>> for ParamValue {
+  fn from(value: Vec<f32>) -> Self {
+    ParamValue::FloatArray(value)
+  }
+}
+
+impl BindUniform for ParamValue {
+  unsafe fn bind_uniform(&self, gl: &Context, shader: &mut ActiveShader, name: &str) {
+    match self {
+      ParamValue::Bool(v) => shader.bind_uniform(gl, name, v),
+      ParamValue::Int(v) => shader.bind_uniform(gl, name, v),
+      ParamValue::Float(v) => shader.bind_uniform(gl, name, v),
+      ParamValue::Vec2(v) => shader.bind_uniform(gl, name, v),
+      ParamValue::Vec3(v) => shader.bind_uniform(gl, name, v),
+      ParamValue::Vec4(v) => shader.bind_uniform(gl, name, v),
+      ParamValue::FloatArray(v) => shader.bind_uniform(gl, name, v),
+    }
+  }
+}
+
+// Convolution weights for post/convolve.frag. Square kernels are stored row by
+// row from the top, separable kernels as the 1D weights run along each axis.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kernel {
+  size: usize,
+  weights: Vec<f32>,
+  separable: bool,
+}
+
+impl Kernel {
+  pub fn new(size: usize, weights: Vec<f32>) -> Result<Self> {
+    if size % 2 == 0 || weights.len() != size * size {
+      bail!(
+        "Kernel must be NxN with odd N, found {} weights for N = {}",
+        weights.len(),
+        size
+      );
+    }
+    if weights.len() > MAX_KERNEL_LEN {
+      bail!(
+        "{}x{} kernel is larger than {} weights",
+        size,
+        size,
+        MAX_KERNEL_LEN
+      );
+    }
+    Ok(Kernel {
+      size,
+      weights,
+      separable: false,
+    })
+  }
+
+  // A 1D kernel applied horizontally then vertically, so an N tap blur costs
+  // 2N samples instead of N^2
+  pub fn separable(weights: Vec<f32>) -> Result<Self> {
+    if weights.len() % 2 == 0 || weights.len() > MAX_KERNEL_LEN {
+      bail!(
+        "Separable kernel needs an odd number of weights up to {}, found {}",
+        MAX_KERNEL_LEN,
+        weights.len()
+      );
+    }
+    Ok(Kernel {
+      size: weights.len(),
+      weights,
+      separable: true,
+    })
+  }
+
+  pub fn box_blur(size: usize) -> Result<Self> {
+    Self::separable(vec![1. / size as f32; size])
+  }
+
+  pub fn gaussian(size: usize) -> Result<Self> {
+    // Cover +-3 standard deviations with the kernel
+    let radius = (size / 2) as f32;
+    let sigma = (radius / 3.).max(0.5);
+    let weights = (0..size)
+      .map(|i| {
+        let x = i as f32 - radius;
+        (-x * x / (2. * sigma * sigma)).exp()
+      })
+      .collect();
+    Ok(Self::separable(weights)?.normalized())
+  }
+
+  pub fn sharpen() -> Self {
+    Self::new(3, vec![0., -1., 0., -1., 5., -1., 0., -1., 0.]).unwrap()
+  }
+
+  pub fn emboss() -> Self {
+    Self::new(3, vec![-2., -1., 0., -1., 1., 1., 0., 1., 2.]).unwrap()
+  }
+
+  pub fn sobel_x() -> Self {
+    Self::new(3, vec![-1., 0., 1., -2., 0., 2., -1., 0., 1.]).unwrap()
+  }
+
+  pub fn sobel_y() -> Self {
+    Self::new(3, vec![1., 2., 1., 0., 0., 0., -1., -2., -1.]).unwrap()
+  }
+
+  pub fn laplacian() -> Self {
+    Self::new(3, vec![0., 1., 0., 1., -4., 1., 0., 1., 0.]).unwrap()
+  }
+
+  // Scale weights to sum to 1 so the kernel keeps overall brightness
+  pub fn normalized(mut self) -> Self {
+    let sum: f32 = self.weights.iter().sum();
+    if sum != 0. {
+      for weight in self.weights.iter_mut() {
+        *weight /= sum;
+      }
+    }
+    self
+  }
+
+  // Parses a kernel name (sharpen, emboss, sobel_x, sobel_y, laplacian, box:N,
+  // gaussian:N) or a list of NxN weights like [0,-1,0,-1,5,-1,0,-1,0]
+  pub fn parse(s: &str) -> Result<Self> {
+    let sized = |name: &str| -> Result<Option<usize>> {
+      match s.strip_prefix(name) {
+        Some(size) => Ok(Some(size.parse()?)),
+        None => Ok(None),
+      }
+    };
+
+    Ok(match s {
+      "sharpen" => Self::sharpen(),
+      "emboss" => Self::emboss(),
+      "sobel_x" => Self::sobel_x(),
+      "sobel_y" => Self::sobel_y(),
+      "laplacian" => Self::laplacian(),
+      _ => {
+        if let Some(size) = sized("box:")? {
+          Self::box_blur(size)?
+        } else if let Some(size) = sized("gaussian:")? {
+          Self::gaussian(size)?
+        } else if s.starts_with('[') {
+          let weights = match ParamValue::parse(s)? {
+            ParamValue::FloatArray(weights) => weights,
+            _ => bail!("Expected a list of weights, found {:?}", s),
+          };
+          let size = (weights.len() as f32).sqrt().round() as usize;
+          Self::new(size, weights)?
+        } else {
+          bail!("Unknown kernel {:?}", s)
+        }
+      }
+    })
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FxaaPreset {
+  Low,
+  Medium,
+  High,
+  Extreme,
+}
+
+impl Default for FxaaPreset {
+  fn default() -> Self {
+    FxaaPreset::High
+  }
+}
+
+impl FxaaPreset {
+  pub fn parse(s: &str) -> Result<Self> {
+    Ok(match s {
+      "low" => FxaaPreset::Low,
+      "medium" => FxaaPreset::Medium,
+      "high" => FxaaPreset::High,
+      "extreme" => FxaaPreset::Extreme,
+      _ => bail!("Unknown FXAA preset {:?}", s),
+    })
+  }
+
+  pub fn next(self) -> Self {
+    match self {
+      FxaaPreset::Low => FxaaPreset::Medium,
+      FxaaPreset::Medium => FxaaPreset::High,
+      FxaaPreset::High => FxaaPreset::Extreme,
+      FxaaPreset::Extreme => FxaaPreset::Low,
+    }
+  }
+
+  // Relative contrast for an edge, minimum absolute contrast (skips dark areas),
+  // sub-pixel blending amount and number of steps searching along an edge.
+  // Steps must not exceed MAX_SEARCH_STEPS in fxaa.frag.
+  fn settings(self) -> (f32, f32, f32, i32) {
+    match self {
+      FxaaPreset::Low => (0.25, 0.0833, 0.5, 4),
+      FxaaPreset::Medium => (0.166, 0.0625, 0.75, 8),
+      FxaaPreset::High => (0.125, 0.0312, 0.75, 12),
+      FxaaPreset::Extreme => (0.063, 0.0312, 1., 12),
+    }
+  }
+}
+
+// A single full-screen effect. Every pass receives the previous pass's output as
+// screenTexture, the scene depth as depthTexture, motion vectors as
+// velocityTexture and the size of one pixel as texel_size, plus its own parameters.
+pub struct PostPass {
+  pub name: String,
+  pub enabled: bool,
+
+  // Pinned passes (like anti-aliasing) are left alone by PostStack::solo
+  pub pinned: bool,
+
+  shader: Shader,
+  params: HashMap<String, ParamValue>,
+
+  // Preset last applied by set_fxaa_preset, if any
+  fxaa_preset: Option<FxaaPreset>,
+
+  // The shader runs once per step, each step reading the previous one's output
+  // with its params layered over the pass params
+  steps: Vec<HashMap<String, ParamValue>>,
+}
+
+impl PostPass {
+  pub fn new(name: impl Into<String>, shader: Shader) -> Self {
+    PostPass {
+      name: name.into(),
+      enabled: true,
+      pinned: false,
+      shader,
+      params: HashMap::new(),
+      fxaa_preset: None,
+      steps: vec![HashMap::new()],
+    }
+  }
+
+  pub async unsafe fn load(
+    gl: &Context,
+    name: impl Into<String>,
+    fragment_path: impl AsRef<std::path::Path>,
+  ) -> Result<Self> {
+    let shader = Shader::load(gl, "assets/shaders/screen.vert", fragment_path, None).await?;
+    Ok(PostPass::new(name, shader))
+  }
+
+  pub fn with_param(mut self, name: impl Into<String>, value: impl Into<ParamValue>) -> Self {
+    self.params.insert(name.into(), value.into());
+    self
+  }
+
+  pub fn with_enabled(mut self, enabled: bool) -> Self {
+    self.enabled = enabled;
+    self
+  }
+
+  pub fn with_kernel(mut self, kernel: &Kernel) -> Self {
+    self.set_kernel(kernel);
+    self
+  }
+
+  // Kernels can change size, so unlike set_param this replaces the old params
+  pub fn set_kernel(&mut self, kernel: &Kernel) {
+    self
+      .params
+      .insert("kernel".into(), kernel.weights.clone().into());
+    self
+      .params
+      .insert("kernel_size".into(), (kernel.size as i32).into());
+    self
+      .params
+      .insert("separable".into(), kernel.separable.into());
+    self.steps = if kernel.separable {
+      vec![
+        hashmap! { "direction".to_owned() => ParamValue::Vec2(glm::vec2(1., 0.)) },
+        hashmap! { "direction".to_owned() => ParamValue::Vec2(glm::vec2(0., 1.)) },
+      ]
+    } else {
+      vec![HashMap::new()]
+    };
+  }
+
+  pub fn set_fxaa_preset(&mut self, preset: FxaaPreset) {
+    let (threshold, threshold_min, subpixel, search_steps) = preset.settings();
+    self
+      .params
+      .insert("edge_threshold".into(), threshold.into());
+    self
+      .params
+      .insert("edge_threshold_min".into(), threshold_min.into());
+    self.params.insert("subpixel".into(), subpixel.into());
+    self
+      .params
+      .insert("search_steps".into(), search_steps.into());
+    self.fxaa_preset = Some(preset);
+  }
+
+  pub fn fxaa_preset(&self) -> Option<FxaaPreset> {
+    self.fxaa_preset
+  }
+
+  pub fn param(&self, name: &str) -> Option<&ParamValue> {
+    self.params.get(name)
+  }
+
+  // Changes an existing parameter. The new value must have the same type.
+  // Kernel params depend on each other, so those go through set_kernel.
+  pub fn set_param(&mut self, name: &str, value: impl Into<ParamValue>) -> Result<()> {
+    if let "kernel" | "kernel_size" | "separable" = name {
+      bail!(
+        "Pass {} parameter {} can only be changed with set_kernel",
+        self.name,
+        name
+      );
+    }
+
+    let value = value.into();
+    match self.params.get_mut(name) {
+      Some(param) if mem::discriminant(param) == mem::discriminant(&value) => {
+        *param = value;
+        Ok(())
+      }
+      Some(param) => bail!(
+        "Pass {} parameter {} is {:?}, can't set it to {:?}",
+        self.name,
+        name,
+        param,
+        value
+      ),
+      None => bail!("Pass {} has no parameter {}", self.name, name),
+    }
+  }
+
+  unsafe fn draw(
+    &self,
+    gl: &Context,
+    step: usize,
+    screen_geom: &Mesh,
+    input: &Texture,
+    depth: &Texture,
+    velocity: &Texture,
+    texel_size: &Vec2,
+  ) {
+    let mut shader = self.shader.activate(gl);
+    shader.bind_uniform(gl, "screenTexture", input);
+
+    // Passes only declare the inputs they read
+    shader.bind_optional_uniform(gl, "depthTexture", depth);
+    shader.bind_optional_uniform(gl, "velocityTexture", velocity);
+    shader.bind_optional_uniform(gl, "texel_size", texel_size);
+    for (name, value) in self.params.iter().chain(self.steps[step].iter()) {
+      shader.bind_uniform(gl, name, value);
+    }
+    screen_geom.draw(gl, &mut shader);
+  }
+}
+
+// Ordered chain of post passes. Enabled passes ping-pong between two render
+// targets, each one reading the output of the one before it.
+pub struct PostStack {
+  passes: Vec<PostPass>,
+  targets: [Framebuffer; 2],
+  texel_size: Vec2,
+}
+
+impl PostStack {
+  pub unsafe fn new(gl: &Context, width: u32, height: u32) -> Result<Self> {
+    Ok(PostStack {
+      passes: vec![],
+      targets: [
+        Framebuffer::new(gl, width, height, false)?,
+        Framebuffer::new(gl, width, height, false)?,
+      ],
+      texel_size: glm::vec2(1. / width as f32, 1. / height as f32),
+    })
+  }
+
+  // Builds a stack from a config file with one pass per line:
+  //   <name> <fragment shader> [param=value ...]
+  // where the shader is relative to assets/shaders, "enabled=false" adds the
+  // pass switched off, "pinned=true" keeps it running through solo,
+  // "kernel=..." takes anything Kernel::parse accepts, "fxaa_preset=..." takes
+  // low/medium/high/extreme, and blank lines and lines starting with # are ignored.
+  pub async unsafe fn load(
+    gl: &Context,
+    config_path: impl AsRef<std::path::Path>,
+    width: u32,
+    height: u32,
+  ) -> Result<Self> {
+    let config_path = config_path.as_ref();
+    let config = io::load_string(config_path).await?;
+
+    let lines = config
+      .lines()
+      .enumerate()
+      .map(|(i, line)| (i + 1, line.trim()))
+      .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+    let passes = try_join_all(lines.map(|(line_number, line)| async move {
+      Self::parse_pass(gl, line)
+        .await
+        .with_context(|| format!("In {:?} line {}", config_path, line_number))
+    }))
+    .await?;
+
+    let mut stack = PostStack::new(gl, width, height)?;
+    stack.passes = passes;
+    Ok(stack)
+  }
+
+  async unsafe fn parse_pass(gl: &Context, line: &str) -> Result<PostPass> {
+    let mut words = line.split_whitespace();
+    let (name, shader) = match (words.next(), words.next()) {
+      (Some(name), Some(shader)) => (name, shader),
+      _ => bail!("Expected <name> <fragment shader>, found {:?}", line),
+    };
+
+    let mut pass = PostPass::load(gl, name, format!("{}/{}", SHADER_DIR, shader)).await?;
+    for word in words {
+      let (key, value) = match word.find('=') {
+        Some(i) => (&word[..i], &word[i + 1..]),
+        None => bail!("Expected param=value, found {:?}", word),
+      };
+      match key {
+        "kernel" => pass.set_kernel(&Kernel::parse(value)?),
+        "fxaa_preset" => pass.set_fxaa_preset(FxaaPreset::parse(value)?),
+        "enabled" | "pinned" => {
+          let flag = match ParamValue::parse(value)? {
+            ParamValue::Bool(flag) => flag,
+            value => bail!("{} must be true or false, found {:?}", key, value),
+          };
+          if key == "enabled" {
+            pass.enabled = flag;
+          } else {
+            pass.pinned = flag;
+          }
+        }
+        _ => pass = pass.with_param(key, ParamValue::parse(value)?),
+      }
+    }
+    Ok(pass)
+  }
+
+  pub fn push(&mut self, pass: PostPass) {
+    self.passes.push(pass);
+  }
+
+  pub fn len(&self) -> usize {
+    self.passes.len()
+  }
+
+  // Number of passes that solo chooses between
+  pub fn num_unpinned(&self) -> usize {
+    self.passes.iter().filter(|pass| !pass.pinned).count()
+  }
+
+  pub fn passes(&self) -> &[PostPass] {
+    &self.passes
+  }
+
+  pub fn pass(&self, name: &str) -> Option<&PostPass> {
+    self.passes.iter().find(|pass| pass.name == name)
+  }
+
+  pub fn pass_mut(&mut self, name: &str) -> Option<&mut PostPass> {
+    self.passes.iter_mut().find(|pass| pass.name == name)
+  }
+
+  pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<()> {
+    let pass = self
+      .pass_mut(name)
+      .with_context(|| format!("No post pass named {}", name))?;
+    pass.enabled = enabled;
+    Ok(())
+  }
+
+  // Moves the pass at index from so that it runs at index to
+  pub fn move_pass(&mut self, from: usize, to: usize) {
+    let pass = self.passes.remove(from);
+    self.passes.insert(to.min(self.passes.len()), pass);
+  }
+
+  // Enables only the unpinned pass at index (counting unpinned passes only), or
+  // none of them. Pinned passes keep their current state.
+  pub fn solo(&mut self, index: Option<usize>) {
+    let unpinned = self.passes.iter_mut().filter(|pass| !pass.pinned);
+    for (i, pass) in unpinned.enumerate() {
+      pass.enabled = Some(i) == index;
+    }
+  }
+
+  // Runs every enabled pass and returns the texture holding the final result
+  pub unsafe fn apply<'a>(
+    &'a self,
+    gl: &Context,
+    screen_geom: &Mesh,
+    input: &'a Texture,
+    depth: &Texture,
+    velocity: &Texture,
+  ) -> &'a Texture {
+    let mut output = input;
+    let steps = self
+      .passes
+      .iter()
+      .filter(|pass| pass.enabled)
+      .flat_map(|pass| (0..pass.steps.len()).map(move |step| (pass, step)));
+    for (i, (pass, step)) in steps.enumerate() {
+      let target = &self.targets[i % 2];
+      gl.bind_framebuffer(glow::FRAMEBUFFER, Some(target.fbo));
+      pass.draw(
+        gl,
+        step,
+        screen_geom,
+        output,
+        depth,
+        velocity,
+        &self.texel_size,
+      );
+      output = &target.render_texture;
+    }
+    output
+  }
+}
@@ -15,3 +15,4 @@ pub type GlUniformLocation = <Context as HasContext>::UniformLocation;
 pub type GlTexture = <Context as HasContext>::Texture;
 pub type GlBuffer = <Context as HasContext>::Buffer;
 pub type GlFramebuffer = <Context as HasContext>::Framebuffer;
+pub type GlQuery = <Context as HasContext>::Query;
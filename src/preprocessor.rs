@@ -0,0 +1,295 @@
+use futures::future::{FutureExt, LocalBoxFuture};
+use std::path::{Component, Path, PathBuf};
+
+use crate::{io, prelude::*};
+
+const PRECISION_QUALIFIERS: &[&str] = &["highp", "mediump", "lowp"];
+
+struct SourceFile {
+  name: String,
+  text: String,
+}
+
+// Shader source with #line directives numbering each file it came from, so
+// that driver errors can be traced back to the original file and line
+pub struct ShaderSource {
+  // Source string 0 is the generated preamble, the rest are files in the
+  // order they were first included
+  files: Vec<SourceFile>,
+  body: String,
+}
+
+impl ShaderSource {
+  // Source that didn't come from a file, e.g. written inline
+  pub fn inline(name: &str, text: String) -> Self {
+    let body = format!("#line 1 1\n{}", text);
+    ShaderSource {
+      files: vec![
+        SourceFile {
+          name: "<preamble>".to_owned(),
+          text: String::new(),
+        },
+        SourceFile {
+          name: name.to_owned(),
+          text,
+        },
+      ],
+      body,
+    }
+  }
+
+  // Name of the file the source starts in
+  pub fn name(&self) -> &str {
+    &self.files[1].name
+  }
+
+  // Names of the uniforms declared outside of blocks, e.g. both a and b in
+  // "uniform highp float a, b[2];"
+  pub fn declared_uniforms(&self) -> impl Iterator<Item = &str> {
+    self
+      .body
+      .lines()
+      .filter_map(|line| {
+        let declaration = line.trim().strip_prefix("uniform ")?.split(';').next()?;
+        let (mut word, mut names) = split_word(declaration);
+        while PRECISION_QUALIFIERS.contains(&word) {
+          let (next, rest) = split_word(names);
+          word = next;
+          names = rest;
+        }
+        Some(names.split(',').filter_map(|name| {
+          // Anything else, like the { of a block, isn't a uniform's name
+          let name = name.split('[').next()?.trim();
+          if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return None;
+          }
+          Some(name)
+        }))
+      })
+      .flatten()
+  }
+
+  // Every file the source came from, in the order they were included
+  pub fn file_names(&self) -> impl Iterator<Item = &str> {
+    self.files[1..].iter().map(|file| file.name.as_str())
+  }
+
+  // Full text to compile, with preamble added before everything else. It
+  // must end in a newline.
+  pub fn with_preamble(&mut self, preamble: String) -> String {
+    let text = format!("{}{}", preamble, self.body);
+    self.files[0].text = preamble;
+    text
+  }
+
+  // Rewrites the locations in a driver's info log as file:line, each
+  // followed by the line of source it points at
+  pub fn annotate(&self, log: &str) -> String {
+    let mut output = vec![];
+    for line in log.lines() {
+      match parse_log_line(line) {
+        Some(location) => output.push(self.describe(&location)),
+        None => output.push(line.to_owned()),
+      }
+    }
+    output.join("\n")
+  }
+
+  fn describe(&self, location: &LogLocation) -> String {
+    let file = match self.files.get(location.source) {
+      Some(file) => file,
+      None => return location.message.clone(),
+    };
+    let mut description = format!("{}:{}", file.name, location.line);
+    if let Some(column) = location.column {
+      description += &format!(":{}", column);
+    }
+    description += &format!(": {}", location.message);
+
+    // Point at the column if the driver gave one, or else the whole line
+    if let Some(source_line) = file.text.lines().nth(location.line.wrapping_sub(1)) {
+      let source_line = source_line.trim_end();
+      let indent = source_line.len() - source_line.trim_start().len();
+      let (start, width) = match location.column {
+        Some(column) => (column.saturating_sub(1).min(source_line.len()), 1),
+        None => (indent, (source_line.len() - indent).max(1)),
+      };
+      let gutter = location.line.to_string();
+      description += &format!(
+        "\n{} | {}\n{} | {}{}",
+        gutter,
+        source_line,
+        " ".repeat(gutter.len()),
+        " ".repeat(start),
+        "^".repeat(width)
+      );
+    }
+    description
+  }
+}
+
+struct LogLocation {
+  source: usize,
+  line: usize,
+  column: Option<usize>,
+  message: String,
+}
+
+// Splits the first whitespace-separated word off s
+fn split_word(s: &str) -> (&str, &str) {
+  let s = s.trim_start();
+  let end = s.find(char::is_whitespace).unwrap_or(s.len());
+  (&s[..end], &s[end..])
+}
+
+// Splits leading digits off s
+fn split_number(s: &str) -> Option<(usize, &str)> {
+  let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+  Some((s[..end].parse().ok()?, &s[end..]))
+}
+
+// Drivers disagree on the format, e.g. "0:12(5): error: ..." on Mesa,
+// "0(12) : error C0000: ..." on Nvidia and "ERROR: 0:12: ..." on ANGLE
+fn parse_log_line(line: &str) -> Option<LogLocation> {
+  let mut rest = line.trim_start();
+  let mut severity = "";
+  for prefix in &["ERROR: ", "WARNING: "] {
+    if let Some(stripped) = rest.strip_prefix(prefix) {
+      severity = *prefix;
+      rest = stripped;
+    }
+  }
+
+  let (source, rest) = split_number(rest)?;
+  let (line, column, rest) = match rest.strip_prefix(':') {
+    Some(rest) => {
+      let (line, rest) = split_number(rest)?;
+      match rest.strip_prefix('(') {
+        Some(rest) => {
+          let (column, rest) = split_number(rest)?;
+          (line, Some(column), rest.strip_prefix(')')?)
+        }
+        None => (line, None, rest),
+      }
+    }
+    None => {
+      let (line, rest) = split_number(rest.strip_prefix('(')?)?;
+      (line, None, rest.strip_prefix(')')?)
+    }
+  };
+  let message = rest.trim_start().strip_prefix(':')?.trim();
+
+  Some(LogLocation {
+    source,
+    line,
+    column,
+    message: format!("{}{}", severity, message),
+  })
+}
+
+// Loads shader source with `#include "file"` directives replaced by the
+// file's contents, resolved relative to the including file. Each file is
+// pasted in at most once, as if every file had an include guard.
+pub async fn load_source(path: impl AsRef<Path>) -> Result<ShaderSource> {
+  let mut stack = vec![];
+  let mut files = vec![SourceFile {
+    name: "<preamble>".to_owned(),
+    text: String::new(),
+  }];
+  let body = expand(normalize(path.as_ref()), &mut stack, &mut files).await?;
+  Ok(ShaderSource { files, body })
+}
+
+// Includes being expanded are on stack, so finding path there means it
+// includes itself
+fn expand<'a>(
+  path: PathBuf,
+  stack: &'a mut Vec<PathBuf>,
+  files: &'a mut Vec<SourceFile>,
+) -> LocalBoxFuture<'a, Result<String>> {
+  async move {
+    if let Some(start) = stack.iter().position(|other| *other == path) {
+      let cycle = stack[start..]
+        .iter()
+        .chain(Some(&path))
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>();
+      bail!("Include cycle: {}", cycle.join(" -> "));
+    }
+    let name = path.display().to_string();
+    if files.iter().any(|file| file.name == name) {
+      return Ok(String::new());
+    }
+
+    let source = io::load_string(&path)
+      .await
+      .context(format!("Loading shader source {:?}", path))?;
+    let dir = path.parent().map(Path::to_owned).unwrap_or_default();
+    let index = files.len();
+    files.push(SourceFile {
+      name,
+      text: source.clone(),
+    });
+
+    stack.push(path.clone());
+    let mut output = String::with_capacity(source.len());
+    output += &format!("#line 1 {}\n", index);
+    for (i, line) in source.lines().enumerate() {
+      match parse_include(line) {
+        Some(Some(file)) => {
+          let expanded = expand(normalize(&dir.join(file)), stack, files)
+            .await
+            .context(format!("Included from {}:{}", path.display(), i + 1))?;
+          output.push_str(&expanded);
+
+          // Carry on numbering from the line after the #include
+          output += &format!("#line {} {}\n", i + 2, index);
+        }
+        Some(None) => bail!(
+          "{}:{}: expected #include \"file\", found {}",
+          path.display(),
+          i + 1,
+          line.trim()
+        ),
+        None => {
+          output.push_str(line);
+          output.push('\n');
+        }
+      }
+    }
+    stack.pop();
+
+    Ok(output)
+  }
+  .boxed_local()
+}
+
+// None if line isn't an #include, Some(None) if it's malformed
+fn parse_include(line: &str) -> Option<Option<&str>> {
+  let rest = line.trim().strip_prefix('#')?.trim_start();
+  let rest = rest.strip_prefix("include")?.trim();
+  Some(
+    rest
+      .strip_prefix('"')
+      .and_then(|rest| rest.strip_suffix('"'))
+      .filter(|file| !file.is_empty()),
+  )
+}
+
+// Resolves "." and ".." so that the same file is always the same path
+fn normalize(path: &Path) -> PathBuf {
+  let mut normalized = PathBuf::new();
+  for component in path.components() {
+    match component {
+      Component::CurDir => {}
+      Component::ParentDir => match normalized.components().next_back() {
+        Some(Component::Normal(_)) => {
+          normalized.pop();
+        }
+        _ => normalized.push(".."),
+      },
+      component => normalized.push(component),
+    }
+  }
+  normalized
+}
@@ -0,0 +1,228 @@
+use crate::prelude::*;
+use instant::Instant;
+use std::collections::VecDeque;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fmt::Write as _, path::Path};
+
+// Frames of GPU queries in flight. A frame's results are read back this many
+// frames later, by which point the GPU has finished them and reading won't stall.
+const QUERY_FRAMES: usize = 3;
+
+// Events kept for trace export, roughly the last few seconds
+const MAX_TRACE_EVENTS: usize = 20_000;
+
+// Weight of the newest sample in the overlay's moving averages
+const SMOOTHING: f32 = 0.1;
+
+struct TraceEvent {
+  name: &'static str,
+  gpu: bool,
+
+  // Microseconds since the profiler was created
+  start: f64,
+  duration: f64,
+}
+
+// GPU scopes issued during one frame, in order
+#[derive(Default)]
+struct QueryFrame {
+  queries: Vec<GlQuery>,
+  names: Vec<&'static str>,
+  cpu_start: f64,
+}
+
+// Times named CPU and GPU scopes each frame. GPU scopes use TIME_ELAPSED
+// queries, which can't nest, so starting one ends the last. CPU scopes nest.
+pub struct Profiler {
+  pub enabled: bool,
+  gpu_supported: bool,
+  epoch: Instant,
+
+  frames: Vec<QueryFrame>,
+  frame: usize,
+  gpu_open: bool,
+  cpu_open: Vec<(&'static str, f64)>,
+
+  // Smoothed milliseconds per scope, in the order scopes were first seen
+  averages: Vec<(&'static str, bool, f32)>,
+  trace: VecDeque<TraceEvent>,
+}
+
+impl Default for Profiler {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Profiler {
+  pub fn new() -> Self {
+    Profiler {
+      enabled: false,
+
+      // WebGL only has timer queries behind an extension glow doesn't expose
+      gpu_supported: cfg!(not(target_arch = "wasm32")),
+      epoch: Instant::now(),
+      frames: (0..QUERY_FRAMES).map(|_| QueryFrame::default()).collect(),
+      frame: 0,
+      gpu_open: false,
+      cpu_open: vec![],
+      averages: vec![],
+      trace: VecDeque::new(),
+    }
+  }
+
+  fn now(&self) -> f64 {
+    self.epoch.elapsed().as_nanos() as f64 / 1e3
+  }
+
+  fn record(&mut self, event: TraceEvent) {
+    let ms = (event.duration / 1e3) as f32;
+    match self
+      .averages
+      .iter_mut()
+      .find(|(name, gpu, _)| *name == event.name && *gpu == event.gpu)
+    {
+      Some((_, _, average)) => *average += (ms - *average) * SMOOTHING,
+      None => self.averages.push((event.name, event.gpu, ms)),
+    }
+
+    if self.trace.len() == MAX_TRACE_EVENTS {
+      self.trace.pop_front();
+    }
+    self.trace.push_back(event);
+  }
+
+  // Collects the results of the oldest frame in flight and reuses its queries
+  pub unsafe fn begin_frame(&mut self, gl: &Context) {
+    let slot = self.frame % QUERY_FRAMES;
+    let frame = &mut self.frames[slot];
+    let names = std::mem::take(&mut frame.names);
+    let queries = frame.queries.clone();
+    let cpu_start = frame.cpu_start;
+
+    // If the GPU is somehow still behind, drop the frame rather than wait
+    let available = names.last().map_or(false, |_| {
+      gl.get_query_parameter_u32(queries[names.len() - 1], glow::QUERY_RESULT_AVAILABLE) != 0
+    });
+    if available {
+      // Only durations are measured, so GPU events are laid end to end from
+      // the start of the frame's CPU work
+      let mut start = cpu_start;
+      for (name, query) in names.into_iter().zip(queries) {
+        let duration = gl.get_query_parameter_u32(query, glow::QUERY_RESULT) as f64 / 1e3;
+        self.record(TraceEvent {
+          name,
+          gpu: true,
+          start,
+          duration,
+        });
+        start += duration;
+      }
+    }
+
+    let now = self.now();
+    self.frames[slot].cpu_start = now;
+  }
+
+  pub unsafe fn end_frame(&mut self, gl: &Context) {
+    self.gpu_end(gl);
+    self.frame += 1;
+  }
+
+  // Starts timing GPU work under name, ending any scope already open
+  pub unsafe fn gpu_begin(&mut self, gl: &Context, name: &'static str) {
+    if !self.enabled || !self.gpu_supported {
+      return;
+    }
+    self.gpu_end(gl);
+
+    let frame = &mut self.frames[self.frame % QUERY_FRAMES];
+    let index = frame.names.len();
+    if index == frame.queries.len() {
+      match gl.create_query() {
+        Ok(query) => frame.queries.push(query),
+        Err(_) => return,
+      }
+    }
+    gl.begin_query(glow::TIME_ELAPSED, frame.queries[index]);
+    frame.names.push(name);
+    self.gpu_open = true;
+  }
+
+  pub unsafe fn gpu_end(&mut self, gl: &Context) {
+    if self.gpu_open {
+      gl.end_query(glow::TIME_ELAPSED);
+      self.gpu_open = false;
+    }
+  }
+
+  pub fn cpu_begin(&mut self, name: &'static str) {
+    if self.enabled {
+      let now = self.now();
+      self.cpu_open.push((name, now));
+    }
+  }
+
+  pub fn cpu_end(&mut self) {
+    if let Some((name, start)) = self.cpu_open.pop() {
+      let duration = self.now() - start;
+      self.record(TraceEvent {
+        name,
+        gpu: false,
+        start,
+        duration,
+      });
+    }
+  }
+
+  // One line per scope with its smoothed time
+  pub fn overlay(&self) -> String {
+    let mut lines = vec![];
+    for &gpu in &[false, true] {
+      let scopes = self.averages.iter().filter(|(_, g, _)| *g == gpu);
+      let total = scopes.clone().map(|(_, _, ms)| ms).sum::<f32>();
+      if gpu && !self.gpu_supported {
+        lines.push("GPU timing unavailable".to_string());
+        continue;
+      }
+      lines.push(format!(
+        "{} {:.2} ms",
+        if gpu { "GPU" } else { "CPU" },
+        total
+      ));
+      for (name, _, ms) in scopes {
+        lines.push(format!("  {:<12} {:.2}", name, ms));
+      }
+    }
+    lines.join("\n")
+  }
+
+  // Writes recent events in the Chrome trace format, for chrome://tracing or
+  // Perfetto. CPU and GPU scopes show up as two threads.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub fn write_trace(&self, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let mut json = String::from("{\"traceEvents\":[\n");
+    for (i, event) in self.trace.iter().enumerate() {
+      if i > 0 {
+        json.push_str(",\n");
+      }
+      // Scope names are identifiers, so they need no escaping
+      write!(
+        json,
+        "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":0,\"tid\":{}}}",
+        event.name,
+        if event.gpu { "gpu" } else { "cpu" },
+        event.start,
+        event.duration,
+        if event.gpu { 1 } else { 0 }
+      )?;
+    }
+    json.push_str("\n]}\n");
+
+    if let Some(dir) = path.parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, json).with_context(|| format!("Failed to write {}", path.display()))
+  }
+}
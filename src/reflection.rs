@@ -0,0 +1,211 @@
+use std::{
+  cell::RefCell,
+  collections::{HashMap, HashSet},
+};
+
+use crate::{camera::CameraBlock, prelude::*, shader::ShaderBlockDef};
+
+// Uniform blocks defined by the crate. The GL version glow wraps has no way to
+// list a program's blocks by name, so these are looked up instead.
+const BLOCK_NAMES: &[&str] = &[CameraBlock::BLOCK_NAME];
+
+pub const INT_TYPES: &[u32] = &[
+  glow::INT,
+  glow::BOOL,
+  // Textures are bound by setting their sampler to a texture unit
+  glow::SAMPLER_2D,
+  glow::SAMPLER_3D,
+  glow::SAMPLER_CUBE,
+  glow::SAMPLER_2D_SHADOW,
+  glow::SAMPLER_2D_ARRAY,
+  glow::INT_SAMPLER_2D,
+  glow::INT_SAMPLER_3D,
+  glow::INT_SAMPLER_CUBE,
+  glow::INT_SAMPLER_2D_ARRAY,
+  glow::UNSIGNED_INT_SAMPLER_2D,
+  glow::UNSIGNED_INT_SAMPLER_3D,
+  glow::UNSIGNED_INT_SAMPLER_CUBE,
+  glow::UNSIGNED_INT_SAMPLER_2D_ARRAY,
+];
+
+fn type_name(gl_type: u32) -> String {
+  match gl_type {
+    glow::FLOAT => "float".to_owned(),
+    glow::FLOAT_VEC2 => "vec2".to_owned(),
+    glow::FLOAT_VEC3 => "vec3".to_owned(),
+    glow::FLOAT_VEC4 => "vec4".to_owned(),
+    glow::FLOAT_MAT3 => "mat3".to_owned(),
+    glow::FLOAT_MAT4 => "mat4".to_owned(),
+    glow::INT => "int".to_owned(),
+    glow::UNSIGNED_INT => "uint".to_owned(),
+    glow::BOOL => "bool".to_owned(),
+    gl_type if INT_TYPES.contains(&gl_type) => "sampler".to_owned(),
+    gl_type => format!("type {:#x}", gl_type),
+  }
+}
+
+// The part of a uniform's name before any index or field, which is what
+// the shader declares
+fn base_name(name: &str) -> &str {
+  name.split(|c| c == '[' || c == '.').next().unwrap_or(name)
+}
+
+pub struct UniformInfo {
+  pub location: GlUniformLocation,
+  pub gl_type: u32,
+
+  // Length if it's an array of a basic type, or else 1
+  pub size: i32,
+}
+
+// A program's active uniforms and blocks, looked up once after linking so
+// binding doesn't have to ask GL every time
+pub struct Reflection {
+  // Program named in warnings
+  name: String,
+
+  // Keyed by every name a uniform can be set by, so arrays of basic types
+  // appear as "weights", "weights[0]", "weights[1]" and so on
+  uniforms: HashMap<String, UniformInfo>,
+  blocks: HashMap<String, u32>,
+
+  // Uniforms in the source, including ones compiled out for being unused,
+  // which can be bound without a warning
+  declared: HashSet<String>,
+
+  // Names already warned about, so each is only reported once
+  warned: RefCell<HashSet<String>>,
+}
+
+impl Reflection {
+  pub unsafe fn new(
+    gl: &Context,
+    program: GlProgram,
+    name: String,
+    declared: HashSet<String>,
+  ) -> Self {
+    let mut uniforms = HashMap::new();
+    for index in 0..gl.get_active_uniforms(program) {
+      let uniform = match gl.get_active_uniform(program, index) {
+        Some(uniform) => uniform,
+        None => continue,
+      };
+
+      // Members of uniform blocks have no location
+      let location = match gl.get_uniform_location(program, &uniform.name) {
+        Some(location) => location,
+        None => continue,
+      };
+
+      // Arrays of basic types are listed once, under their first element
+      match uniform.name.strip_suffix("[0]") {
+        Some(array) => {
+          for element in 0..uniform.size {
+            let element_name = format!("{}[{}]", array, element);
+            if let Some(location) = gl.get_uniform_location(program, &element_name) {
+              let info = UniformInfo {
+                location,
+                gl_type: uniform.utype,
+                size: 1,
+              };
+              uniforms.insert(element_name, info);
+            }
+          }
+          let info = UniformInfo {
+            location,
+            gl_type: uniform.utype,
+            size: uniform.size,
+          };
+          uniforms.insert(array.to_owned(), info);
+        }
+        None => {
+          let info = UniformInfo {
+            location,
+            gl_type: uniform.utype,
+            size: uniform.size,
+          };
+          uniforms.insert(uniform.name, info);
+        }
+      }
+    }
+
+    let blocks = BLOCK_NAMES
+      .iter()
+      .filter_map(|&block| {
+        let index = gl.get_uniform_block_index(program, block)?;
+        Some((block.to_owned(), index))
+      })
+      .collect();
+
+    Reflection {
+      name,
+      uniforms,
+      blocks,
+      declared,
+      warned: RefCell::new(HashSet::new()),
+    }
+  }
+
+  pub fn uniform(&self, name: &str) -> Option<&UniformInfo> {
+    self.uniforms.get(name)
+  }
+
+  // Whether binding name would reach an active uniform, or the uniform it's
+  // part of is declared but unused, which makes binding it a quiet no-op
+  pub fn has_uniform(&self, name: &str) -> bool {
+    let active = self.uniforms.keys().any(|key| {
+      key.strip_prefix(name).map_or(false, |rest| {
+        rest.is_empty() || rest.starts_with(&['.', '['][..])
+      })
+    });
+    active || self.compiled_out(name)
+  }
+
+  // Declared but with no active fields or elements, so the compiler removed
+  // all of it. Once any part of it is active, every name has to match.
+  fn compiled_out(&self, name: &str) -> bool {
+    let base = base_name(name);
+    self.declared.contains(base) && !self.uniforms.keys().any(|key| base_name(key) == base)
+  }
+
+  // Location to upload a value of one of types to, or None with a warning
+  // if there's no such uniform or it's another type
+  pub fn location(&self, name: &str, types: &[u32]) -> Option<&GlUniformLocation> {
+    match self.uniforms.get(name) {
+      Some(uniform) if types.contains(&uniform.gl_type) => Some(&uniform.location),
+      Some(uniform) => {
+        self.warn(
+          name,
+          &format!(
+            "is a {} but was bound with a {}",
+            type_name(uniform.gl_type),
+            type_name(types[0])
+          ),
+        );
+        None
+      }
+      None => {
+        if !self.declared.contains(base_name(name)) {
+          self.warn(name, "isn't declared");
+        } else if !self.compiled_out(name) {
+          self.warn(name, "isn't active");
+        }
+        None
+      }
+    }
+  }
+
+  pub fn block_location(&self, name: &str) -> Option<u32> {
+    let index = self.blocks.get(name).copied();
+    if index.is_none() && !BLOCK_NAMES.contains(&name) {
+      self.warn(name, "isn't an active uniform block");
+    }
+    index
+  }
+
+  fn warn(&self, name: &str, problem: &str) {
+    if self.warned.borrow_mut().insert(name.to_owned()) {
+      eprintln!("Warning: {}: uniform {} {}", self.name, name, problem);
+    }
+  }
+}
@@ -0,0 +1,137 @@
+use std::path::Path;
+
+use crate::{
+  prelude::*,
+  shader::{ActiveShader, Shader},
+};
+
+// Debug visualizations drawn over every entity, independent of its material
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RenderMode {
+  Shaded,
+  Wireframe,
+  VertexNormals,
+  FaceNormals,
+  UvChecker,
+  MaterialId,
+}
+
+impl RenderMode {
+  // Wireframes need glPolygonMode and normals need a geometry shader, neither of
+  // which exist in WebGL2
+  pub fn available() -> Vec<RenderMode> {
+    if cfg!(target_arch = "wasm32") {
+      vec![
+        RenderMode::Shaded,
+        RenderMode::UvChecker,
+        RenderMode::MaterialId,
+      ]
+    } else {
+      vec![
+        RenderMode::Shaded,
+        RenderMode::Wireframe,
+        RenderMode::VertexNormals,
+        RenderMode::FaceNormals,
+        RenderMode::UvChecker,
+        RenderMode::MaterialId,
+      ]
+    }
+  }
+
+  pub fn cycle(self, reverse: bool) -> RenderMode {
+    let modes = Self::available();
+    let n = modes.len();
+    let i = modes.iter().position(|mode| *mode == self).unwrap_or(0);
+    let next = if reverse { i + n - 1 } else { i + 1 };
+    modes[next % n]
+  }
+}
+
+pub struct RenderModes {
+  pub mode: RenderMode,
+  flat_shader: Shader,
+  normals_shader: Option<Shader>,
+}
+
+impl RenderModes {
+  pub async unsafe fn load(gl: &Context) -> Result<Self> {
+    let (flat_shader, normals_shader) = try_join!(
+      Shader::load(
+        gl,
+        "assets/shaders/debug_view.vert",
+        "assets/shaders/debug_view.frag",
+        None
+      ),
+      async {
+        if cfg!(target_arch = "wasm32") {
+          Ok(None)
+        } else {
+          Shader::load(
+            gl,
+            "assets/shaders/normals.vert",
+            "assets/shaders/debug.frag",
+            Some(Path::new("assets/shaders/normals.geom")),
+          )
+          .await
+          .map(Some)
+        }
+      }
+    )?;
+
+    Ok(RenderModes {
+      mode: RenderMode::Shaded,
+      flat_shader,
+      normals_shader,
+    })
+  }
+
+  pub fn shaders(&self) -> Vec<&Shader> {
+    let mut shaders = vec![&self.flat_shader];
+    shaders.extend(self.normals_shader.as_ref());
+    shaders
+  }
+
+  // Activates the shader for the current mode and sets up GL state. Returns None when
+  // nothing extra needs to be drawn.
+  pub unsafe fn begin<'a>(&'a self, gl: &Context) -> Option<ActiveShader<'a>> {
+    let mut shader = match self.mode {
+      RenderMode::Shaded => {
+        return None;
+      }
+      RenderMode::VertexNormals | RenderMode::FaceNormals => {
+        let mut shader = self.normals_shader.as_ref()?.activate(gl);
+        shader.bind_uniform(gl, "face_normals", &(self.mode == RenderMode::FaceNormals));
+        shader.bind_uniform(gl, "normal_length", &0.1f32);
+        shader
+      }
+      RenderMode::Wireframe => {
+        // Pull lines slightly towards the camera so they win the depth test
+        gl.polygon_mode(glow::FRONT_AND_BACK, glow::LINE);
+        gl.enable(glow::POLYGON_OFFSET_LINE);
+        gl.polygon_offset(-1., -1.);
+        let mut shader = self.flat_shader.activate(gl);
+        shader.bind_uniform(gl, "mode", &0);
+        shader
+      }
+      RenderMode::UvChecker => {
+        let mut shader = self.flat_shader.activate(gl);
+        shader.bind_uniform(gl, "mode", &1);
+        shader
+      }
+      RenderMode::MaterialId => {
+        let mut shader = self.flat_shader.activate(gl);
+        shader.bind_uniform(gl, "mode", &2);
+        shader
+      }
+    };
+    shader.bind_uniform(gl, "wire_color", &glm::vec4(0.1, 1., 0.3, 1.));
+    Some(shader)
+  }
+
+  pub unsafe fn end(&self, gl: &Context) {
+    if self.mode == RenderMode::Wireframe {
+      gl.polygon_mode(glow::FRONT_AND_BACK, glow::FILL);
+      gl.disable(glow::POLYGON_OFFSET_LINE);
+    }
+  }
+}
@@ -0,0 +1,168 @@
+use crate::{
+  camera::Camera,
+  post_process::PostStack,
+  prelude::*,
+  profiler::Profiler,
+  scene::Scene,
+  screen_capture::{DepthView, ScreenCapture},
+  ssr::Ssr,
+  stats::StatsHud,
+  taa::Taa,
+  volumetrics::Volumetrics,
+};
+
+// Everything needed to turn a scene into a finished frame, shared by the
+// windowed and headless entry points
+pub struct Renderer {
+  screen_capture: ScreenCapture,
+  pub post: PostStack,
+  pub taa: Taa,
+  pub ssr: Ssr,
+  pub volumetrics: Volumetrics,
+  pub depth_view: DepthView,
+  pub profiler: Profiler,
+  pub stats: StatsHud,
+  width: u32,
+  height: u32,
+}
+
+impl Renderer {
+  // An offscreen renderer draws its final image into a framebuffer of its own,
+  // see output_fbo
+  pub async unsafe fn load(
+    gl: &Context,
+    scene: &Scene,
+    width: u32,
+    height: u32,
+    offscreen: bool,
+  ) -> Result<Self> {
+    // Turn on OpenGL features
+    gl.enable(glow::DEPTH_TEST);
+    gl.enable(glow::STENCIL_TEST);
+    gl.enable(glow::BLEND);
+    gl.enable(glow::CULL_FACE);
+
+    gl.depth_func(glow::LEQUAL);
+    gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+
+    let screen_capture = ScreenCapture::new(gl, width, height, offscreen).await?;
+    let post = PostStack::load(gl, "assets/post.cfg", width, height).await?;
+    let taa = Taa::load(gl, width, height, screen_capture.depth_texture()).await?;
+    let ssr = Ssr::load(
+      gl,
+      width,
+      height,
+      screen_capture.depth_texture(),
+      scene.skybox_texture(),
+    )
+    .await?;
+    let volumetrics = Volumetrics::load(gl, width, height, screen_capture.depth_texture()).await?;
+    for shader in taa
+      .shaders()
+      .into_iter()
+      .chain(ssr.shaders())
+      .chain(volumetrics.shaders())
+    {
+      scene.bind_camera_block(gl, shader);
+    }
+
+    Ok(Renderer {
+      screen_capture,
+      post,
+      taa,
+      ssr,
+      volumetrics,
+      depth_view: DepthView::Off,
+      profiler: Profiler::new(),
+      stats: StatsHud::default(),
+      width,
+      height,
+    })
+  }
+
+  pub fn size(&self) -> (u32, u32) {
+    (self.width, self.height)
+  }
+
+  // Framebuffer holding the last finished frame, None being the default framebuffer
+  pub fn output_fbo(&self) -> Option<GlFramebuffer> {
+    self.screen_capture.output_fbo()
+  }
+
+  // Text for the stats and profiler overlays, if either is on
+  pub fn overlay(&self) -> Option<String> {
+    let mut sections = vec![];
+    if self.stats.enabled {
+      sections.push(self.stats.text());
+    }
+    if self.profiler.enabled {
+      sections.push(self.profiler.overlay());
+    }
+    if sections.is_empty() {
+      None
+    } else {
+      Some(sections.join("\n\n"))
+    }
+  }
+
+  pub unsafe fn draw(
+    &mut self,
+    gl: &Context,
+    scene: &mut Scene,
+    camera: &mut Camera,
+    time: f32,
+  ) -> Result<()> {
+    let profiler = &mut self.profiler;
+    profiler.begin_frame(gl);
+    profiler.cpu_begin("draw");
+
+    // Water reflection and refraction are whole scene renders of their own
+    profiler.gpu_begin(gl, "water_passes");
+    scene.draw_water_passes(gl, camera, time);
+    gl.viewport(0, 0, self.width as i32, self.height as i32);
+
+    self.taa.begin_frame(camera);
+    self.screen_capture.record(gl);
+
+    // Clear the screen with a default color
+    gl.clear_color(0.1, 0.1, 0.1, 1.0);
+    gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT | glow::STENCIL_BUFFER_BIT);
+    gl.enable(glow::DEPTH_TEST);
+
+    // Draw the scene
+    scene.draw(gl, camera, time, self.width, self.height, profiler)?;
+    profiler.gpu_begin(gl, "velocity");
+    self
+      .taa
+      .draw_velocity(gl, self.screen_capture.screen_geom(), scene, camera);
+    profiler.gpu_begin(gl, "ssr_normals");
+    self.ssr.draw_normals(gl, scene);
+
+    // Reflections, light scattering, TAA resolve and the post stack all run
+    // in replay
+    profiler.gpu_begin(gl, "post");
+    self.volumetrics.update_lights(scene);
+    if self.depth_view == DepthView::Off {
+      self.screen_capture.replay(
+        gl,
+        camera,
+        &self.post,
+        &self.taa,
+        &self.ssr,
+        &self.volumetrics,
+      );
+    } else {
+      self
+        .screen_capture
+        .replay_depth(gl, camera, self.depth_view);
+    }
+
+    scene.end_frame();
+    camera.end_frame();
+
+    profiler.cpu_end();
+    profiler.end_frame(gl);
+    self.stats.end_frame();
+    Ok(())
+  }
+}
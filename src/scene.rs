@@ -1,43 +1,124 @@
-use std::{collections::HashMap, path::Path};
+use std::{collections::HashMap, iter, path::Path, rc::Rc};
 
 use crate::{
+  animation::{
+    AnimationClip, Animator, Channel, Interpolation, Joint, JointTrack, Skeleton, Transform,
+  },
+  billboard::{Billboard, BillboardMode, BillboardRenderer, SpriteSheet},
   camera::{Camera, CameraBlock},
+  debug_draw::DebugDraw,
   geometry::Geometry,
   light::{DirLight, PointLight, SpotLight},
   material::Material,
-  mesh::Mesh,
+  mesh::{Mesh, VertexSkin},
   model::Model,
+  particles::{Curve, EmitterConfig, ParticleEmitter, ParticleRenderer, Simulation},
   prelude::*,
+  profiler::Profiler,
+  render_mode::RenderModes,
   shader::{ActiveShader, Shader, UniformBlock},
+  sky::{ProceduralSky, SkyMode, TimeOfDay},
+  terrain::{Terrain, TerrainConfig},
   text::{Font, Text},
   texture::{TCubemap, Texture, TextureBuilder},
+  water::{Water, WaterConfig},
 };
 
 struct Entity {
   model: Model,
   transform: Mat4,
+
+  // Transform as of the last drawn frame, for motion vectors
+  prev_transform: Mat4,
+
+  emitters: Vec<ParticleEmitter>,
+  animator: Option<Animator>,
 }
 
 impl Entity {
+  fn new(model: Model, transform: Mat4) -> Self {
+    Entity {
+      model,
+      transform,
+      prev_transform: transform,
+      emitters: vec![],
+      animator: None,
+    }
+  }
+
+  fn with_emitter(mut self, emitter: ParticleEmitter) -> Self {
+    self.emitters.push(emitter);
+    self
+  }
+
+  fn with_animator(mut self, animator: Animator) -> Self {
+    self.animator = Some(animator);
+    self
+  }
+
+  fn update(&mut self, dt: f32, camera: &Camera) {
+    self.model.update_lod(&self.transform, camera);
+    if let Some(animator) = self.animator.as_mut() {
+      animator.update(dt);
+    }
+
+    for emitter in self.emitters.iter_mut() {
+      emitter.update(dt, &self.transform, &camera.pos);
+    }
+  }
+
   unsafe fn draw(&self, gl: &Context, shader: &mut ActiveShader) {
     shader.bind_uniform(gl, "model", &self.transform);
+    if let Some(animator) = self.animator.as_ref() {
+      shader.bind_uniform(gl, "joint_matrices", animator.joint_matrices());
+    }
+    self.model.draw(gl, shader);
+  }
+
+  // Draw with both the current and last frame's transforms bound, for shaders
+  // that use them
+  unsafe fn draw_with_history(&self, gl: &Context, shader: &mut ActiveShader) {
+    shader.bind_uniform(gl, "model", &self.transform);
+    shader.bind_optional_uniform(gl, "prev_model", &self.prev_transform);
+    shader.bind_uniform(gl, "skinned", &self.animator.is_some());
+    if let Some(animator) = self.animator.as_ref() {
+      shader.bind_uniform(gl, "joint_matrices", animator.joint_matrices());
+      shader.bind_optional_uniform(gl, "prev_joint_matrices", animator.prev_joint_matrices());
+    }
     self.model.draw(gl, shader);
   }
+
+  fn end_frame(&mut self) {
+    self.prev_transform = self.transform;
+    if let Some(animator) = self.animator.as_mut() {
+      animator.end_frame();
+    }
+  }
 }
 
 pub struct Scene {
   floor: Entity,
   cubes: Vec<Entity>,
-  grasses: Vec<Entity>,
+  billboards: Vec<Billboard>,
   exploder: Entity,
+  skinned: Vec<Entity>,
+  terrain: Terrain,
+  water: Water,
 
   light_shader: Shader,
+  skinned_shader: Shader,
   point_lights: Vec<PointLight>,
   spot_lights: Vec<SpotLight>,
   dir_lights: Vec<DirLight>,
 
+  // Lights only drawn as debug gizmos, which don't light anything
+  gizmo_point_lights: Vec<PointLight>,
+
   text_shader: Shader,
   text: Text,
+  overlay: Option<Text>,
+  shader_errors: Option<Text>,
+  notice: Option<Text>,
   fonts: HashMap<String, Font>,
 
   camera_ubo: UniformBlock<CameraBlock>,
@@ -45,6 +126,18 @@ pub struct Scene {
   skybox_shader: Shader,
   skybox: Mesh,
   skybox_texture: Texture<TCubemap>,
+  procedural_sky: ProceduralSky,
+  pub sky: SkyMode,
+  pub time_of_day: TimeOfDay,
+
+  // Sun to restore when switching back from the procedural sky
+  cubemap_sun: DirLight,
+
+  particle_renderer: ParticleRenderer,
+  billboard_renderer: BillboardRenderer,
+
+  pub debug_draw: DebugDraw,
+  pub render_modes: RenderModes,
 }
 
 impl Scene {
@@ -52,14 +145,22 @@ impl Scene {
     // Load all the assets
     let (
       light_shader,
+      skinned_shader,
       text_shader,
       skybox_shader,
       metal_texture,
       marble_texture,
+      container_texture,
       grass_texture,
+      flame_texture,
       skybox_texture,
       font,
       backpack_model,
+      particle_renderer,
+      billboard_renderer,
+      procedural_sky,
+      debug_draw,
+      render_modes,
     ) = try_join!(
       Shader::load(
         gl,
@@ -71,6 +172,12 @@ impl Scene {
           Some(Path::new("assets/shaders/explode.geom"))
         }
       ),
+      Shader::load(
+        gl,
+        "assets/shaders/skinned.vert",
+        "assets/shaders/colors.frag",
+        None
+      ),
       Shader::load(
         gl,
         "assets/shaders/text.vert",
@@ -85,10 +192,17 @@ impl Scene {
       ),
       TextureBuilder::new(gl).load("assets/textures/metal.png"),
       TextureBuilder::new(gl).load("assets/textures/marble.jpg"),
+      TextureBuilder::new(gl).load("assets/textures/container.jpg"),
       TextureBuilder::new(gl)
         .with_tex_parameter(glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE)
         .with_tex_parameter(glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE)
         .load("assets/textures/blending_transparent_window.png"),
+      // Frames are packed edge to edge, so mipmaps would bleed between them
+      TextureBuilder::new(gl)
+        .with_tex_parameter(glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE)
+        .with_tex_parameter(glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE)
+        .with_tex_parameter(glow::TEXTURE_MIN_FILTER, glow::LINEAR)
+        .load("assets/textures/flame_sheet.png"),
       TextureBuilder::new(gl).as_cubemap().load(
         vec!["right", "left", "top", "bottom", "front", "back"]
           .into_iter()
@@ -96,9 +210,52 @@ impl Scene {
           .collect::<Vec<_>>()
       ),
       Font::load(gl, "assets/fonts/DejaVuSans.ttf"),
-      Model::load(gl, "assets/models/backpack")
+      Model::load(gl, "assets/models/backpack"),
+      ParticleRenderer::load(gl),
+      BillboardRenderer::load(gl),
+      ProceduralSky::load(gl),
+      DebugDraw::load(gl),
+      RenderModes::load(gl)
     )?;
 
+    // Hills around a lake under the floor, with metal in the valley, wood on the slopes
+    // and marble on the peaks and cliffs
+    let layer = |texture: &Texture, shininess| Material {
+      diffuse: texture.clone(),
+      specular: texture.clone(),
+      shininess,
+      reflectivity: 0.,
+    };
+    let terrain = Terrain::load(
+      gl,
+      "assets/textures/terrain_height.png",
+      "assets/textures/terrain_splat.png",
+      vec![
+        layer(&metal_texture, 16.),
+        layer(&container_texture, 4.),
+        layer(&marble_texture, 32.),
+      ],
+      TerrainConfig {
+        size: glm::vec2(64., 64.),
+        height_scale: 8.,
+        chunk_cells: 16,
+        tile_size: 2.,
+      },
+      glm::translation(&glm::vec3(0., -1.5, 0.)),
+    )
+    .await?;
+
+    let water = Water::load(
+      gl,
+      WaterConfig {
+        size: glm::vec2(40., 40.),
+        position: glm::vec3(0., -0.8, 0.),
+        cells: 160,
+        ..Default::default()
+      },
+    )
+    .await?;
+
     let plane_model = Geometry::Plane {
       length: 10.,
       width: 10.,
@@ -110,13 +267,11 @@ impl Scene {
         diffuse: metal_texture.clone(),
         specular: metal_texture,
         shininess: 16.,
+        reflectivity: 0.4,
       }),
     )?
     .to_model();
-    let plane = Entity {
-      model: plane_model,
-      transform: glm::translation(&glm::vec3(0., -0.5, 0.)),
-    };
+    let plane = Entity::new(plane_model, glm::translation(&glm::vec3(0., -0.5, 0.)));
 
     let box_model = Geometry::Cube {
       length: 1.,
@@ -127,37 +282,75 @@ impl Scene {
       gl,
       Some(Material {
         diffuse: marble_texture.clone(),
-        specular: marble_texture,
+        specular: marble_texture.clone(),
         shininess: 16.,
+        reflectivity: 0.,
       }),
     )?
     .to_model();
 
-    let cube1 = Entity {
-      model: box_model.clone(),
-      transform: glm::translation(&glm::vec3(-1., 0., -1.)),
-    };
-    let cube2 = Entity {
-      model: box_model.clone(),
-      transform: glm::translation(&glm::vec3(2., 0., 0.)),
-    };
+    let column = Self::build_skinned_column(
+      gl,
+      Material {
+        diffuse: marble_texture.clone(),
+        specular: marble_texture,
+        shininess: 16.,
+        reflectivity: 0.,
+      },
+    )?;
 
-    let grass_model = Geometry::Plane {
-      length: 1.0,
-      width: 1.0,
-      normal: glm::vec3(0., 1., 0.),
-    }
-    .to_mesh(
+    // Sparks are simulated with transform feedback, smoke on the CPU
+    let sparks = ParticleEmitter::new(
       gl,
-      Some(Material {
-        diffuse: grass_texture.clone(),
-        specular: grass_texture,
-        shininess: 0.,
-      }),
-    )?
-    .to_model();
+      EmitterConfig {
+        rate: 80.,
+        max_particles: 512,
+        lifetime: (0.5, 1.2),
+        speed: (2., 3.5),
+        spread: 0.5,
+        gravity: glm::vec3(0., -4., 0.),
+        color: Curve::new(vec![
+          (0., glm::vec4(1., 0.9, 0.5, 1.)),
+          (0.5, glm::vec4(1., 0.5, 0.1, 1.)),
+          (1., glm::vec4(0.8, 0.1, 0., 0.)),
+        ])?,
+        size: Curve::constant(0.05),
+        additive: true,
+        ..Default::default()
+      },
+      glm::vec3(0., 0.5, 0.),
+      Simulation::Gpu,
+    )?;
+    let smoke = ParticleEmitter::new(
+      gl,
+      EmitterConfig {
+        rate: 15.,
+        lifetime: (2., 3.),
+        speed: (0.2, 0.4),
+        spread: 0.4,
+        gravity: glm::vec3(0., 0.1, 0.),
+        color: Curve::new(vec![
+          (0., glm::vec4(0.5, 0.5, 0.5, 0.)),
+          (0.1, glm::vec4(0.5, 0.5, 0.5, 0.6)),
+          (1., glm::vec4(0.3, 0.3, 0.3, 0.)),
+        ])?,
+        size: Curve::linear(0.2, 0.8),
+        ..Default::default()
+      },
+      glm::vec3(0., 0.5, 0.),
+      Simulation::Cpu,
+    )?;
+
+    let cube1 = Entity::new(
+      box_model.clone(),
+      glm::translation(&glm::vec3(-1., 0., -1.)),
+    )
+    .with_emitter(sparks);
+    let cube2 =
+      Entity::new(box_model.clone(), glm::translation(&glm::vec3(2., 0., 0.))).with_emitter(smoke);
 
-    let grasses = vec![
+    // Grass stays upright while turning to face the camera
+    let mut billboards = vec![
       glm::vec3(-1., 0., -0.48),
       glm::vec3(2.0, 0.0, 0.51),
       glm::vec3(0.0, 0.0, 0.7),
@@ -166,17 +359,10 @@ impl Scene {
     ]
     .into_iter()
     .map(|pos| {
-      let transform = glm::rotate(
-        &glm::translation(&pos),
-        -90_f32.to_radians(),
-        &glm::vec3(1., 0., 0.),
-      );
-      Entity {
-        model: grass_model.clone(),
-        transform,
-      }
+      Billboard::new(grass_texture.clone(), pos, glm::vec2(1., 1.))
+        .with_mode(BillboardMode::Cylindrical)
     })
-    .collect();
+    .collect::<Vec<_>>();
 
     let sun = DirLight {
       direction: glm::vec3(-1., -1., -1.),
@@ -185,6 +371,48 @@ impl Scene {
       specular: glm::vec3(1., 1., 1.),
     };
 
+    // Shown with debug drawing only, so the scene's shading stays the same
+    let lamp = PointLight {
+      position: glm::vec3(3., 0.5, 1.5),
+      ambient: glm::vec3(0.05, 0.04, 0.02),
+      diffuse: glm::vec3(1., 0.7, 0.3),
+      specular: glm::vec3(1., 0.7, 0.3),
+      constant: 1.,
+      linear: 0.35,
+      quadratic: 0.44,
+    };
+
+    // A real light, unlike the lamp, since spot fog only scatters light from
+    // the scene's spot lights
+    let spot = SpotLight {
+      position: glm::vec3(-2., 3., -2.),
+      direction: glm::vec3(0.3, -1., 0.3),
+      inner_cut_off: 12.5_f32.to_radians().cos(),
+      outer_cut_off: 17.5_f32.to_radians().cos(),
+      ambient: glm::zero(),
+      diffuse: glm::vec3(0.6, 0.7, 1.),
+      specular: glm::vec3(0.6, 0.7, 1.),
+      constant: 1.,
+      linear: 0.09,
+      quadratic: 0.032,
+    };
+
+    // Animated flame at the lamp
+    billboards.push(
+      Billboard::new(
+        flame_texture,
+        lamp.position - glm::vec3(0., 0.15, 0.),
+        glm::vec2(0.3, 0.45),
+      )
+      .with_lighting(false)
+      .with_sprite_sheet(SpriteSheet {
+        columns: 4,
+        rows: 2,
+        frames: 8,
+        fps: 12.,
+      }),
+    );
+
     let fonts = hashmap! {
       font.name.clone() => font
     };
@@ -214,48 +442,345 @@ impl Scene {
     light_shader
       .activate(gl)
       .bind_uniform(gl, "CameraBlock", &camera_ubo);
+    skinned_shader
+      .activate(gl)
+      .bind_uniform(gl, "CameraBlock", &camera_ubo);
     skybox_shader
       .activate(gl)
       .bind_uniform(gl, "CameraBlock", &camera_ubo);
+    terrain
+      .shader()
+      .activate(gl)
+      .bind_uniform(gl, "CameraBlock", &camera_ubo);
+    water
+      .shader()
+      .activate(gl)
+      .bind_uniform(gl, "CameraBlock", &camera_ubo);
+    particle_renderer
+      .shader()
+      .activate(gl)
+      .bind_uniform(gl, "CameraBlock", &camera_ubo);
+    billboard_renderer
+      .shader()
+      .activate(gl)
+      .bind_uniform(gl, "CameraBlock", &camera_ubo);
+    procedural_sky
+      .shader()
+      .activate(gl)
+      .bind_uniform(gl, "CameraBlock", &camera_ubo);
+    debug_draw
+      .shader()
+      .activate(gl)
+      .bind_uniform(gl, "CameraBlock", &camera_ubo);
+    for shader in render_modes.shaders() {
+      shader
+        .activate(gl)
+        .bind_uniform(gl, "CameraBlock", &camera_ubo);
+    }
 
-    let exploder = Entity {
-      model: backpack_model,
-      transform: glm::translation(&glm::vec3(1.5, 3., 1.5)),
-    };
+    // The backpack is detailed enough to be worth simplifying at a distance
+    let backpack_model = backpack_model.with_lods(gl, &[(0.5, 0.3), (0.2, 0.15), (0.05, 0.05)])?;
+    let exploder = Entity::new(backpack_model, glm::translation(&glm::vec3(1.5, 3., 1.5)));
 
     Ok(Scene {
       floor: plane,
       cubes: vec![cube1, cube2],
-      grasses,
+      billboards,
       point_lights: vec![],
-      spot_lights: vec![],
-      dir_lights: vec![sun],
+      spot_lights: vec![spot],
+      dir_lights: vec![sun.clone()],
+      gizmo_point_lights: vec![lamp],
       text_shader,
       light_shader,
       skybox_shader,
       fonts,
       text,
+      overlay: None,
+      shader_errors: None,
+      notice: None,
       skybox,
       skybox_texture,
+      procedural_sky,
+      sky: SkyMode::Cubemap,
+      time_of_day: TimeOfDay::default(),
+      cubemap_sun: sun,
       camera_ubo,
       exploder,
+      skinned: vec![column],
+      terrain,
+      water,
+      skinned_shader,
+      particle_renderer,
+      billboard_renderer,
+      debug_draw,
+      render_modes,
     })
   }
 
-  pub fn update(&mut self, _elapsed: f32, _camera: &Camera) {}
+  // A segmented column bent by a two-joint skeleton, showing clip blending
+  unsafe fn build_skinned_column(gl: &Context, material: Material) -> Result<Entity> {
+    let segments = 4;
+    let segment_height = 0.5;
+    let (segment_vertices, segment_indices) = Geometry::Cube {
+      length: 0.3,
+      width: 0.3,
+      height: segment_height,
+    }
+    .to_vertices_indices();
+
+    // Stack segments from y = 0 to y = 2, blending from the root joint to the upper joint
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    let mut skin = vec![];
+    for i in 0..segments {
+      let y_offset = segment_height * (i as f32 + 0.5);
+      indices.extend(
+        segment_indices
+          .iter()
+          .map(|index| index + vertices.len() as u32),
+      );
+      for vertex in segment_vertices.iter() {
+        let mut vertex = vertex.clone();
+        vertex.position.y += y_offset;
+        let upper = (vertex.position.y - 0.5).max(0.).min(1.);
+        skin.push(VertexSkin {
+          joints: [0, 1, 0, 0],
+          weights: glm::vec4(1. - upper, upper, 0., 0.),
+        });
+        vertices.push(vertex);
+      }
+    }
+    let model = Mesh::new_skinned(gl, vertices, indices, skin, Some(material))?.to_model();
+
+    let skeleton = Rc::new(Skeleton::new(vec![
+      Joint {
+        name: "root".to_owned(),
+        parent: None,
+        rest: Transform::identity(),
+        inverse_bind: glm::identity(),
+      },
+      Joint {
+        name: "upper".to_owned(),
+        parent: Some(0),
+        rest: Transform {
+          translation: glm::vec3(0., 1., 0.),
+          ..Transform::identity()
+        },
+        inverse_bind: glm::translation(&glm::vec3(0., -1., 0.)),
+      },
+    ])?);
+
+    let rotations = |axis: Vec3, angles: &[(f32, f32)]| {
+      Channel::new(
+        angles
+          .iter()
+          .map(|(time, angle)| (*time, glm::quat_angle_axis(angle.to_radians(), &axis)))
+          .collect(),
+        Interpolation::Linear,
+      )
+    };
+    let sway = Rc::new(AnimationClip::new(
+      "sway",
+      vec![JointTrack {
+        joint: 1,
+        translation: None,
+        rotation: Some(rotations(
+          glm::vec3(0., 0., 1.),
+          &[(0., 0.), (1., 30.), (2., 0.), (3., -30.), (4., 0.)],
+        )?),
+        scale: None,
+      }],
+    ));
+    let twist = Rc::new(AnimationClip::new(
+      "twist",
+      vec![JointTrack {
+        joint: 0,
+        translation: None,
+        rotation: Some(rotations(
+          glm::vec3(0., 1., 0.),
+          &[(0., 0.), (1.5, 90.), (3., 0.)],
+        )?),
+        scale: None,
+      }],
+    ));
+
+    let mut animator = Animator::new(skeleton);
+    animator.play(sway, true, 0.);
+    animator.blend(twist, 0.5, true, 1.);
+
+    Ok(Entity::new(model, glm::translation(&glm::vec3(-2.5, -0.5, 1.5))).with_animator(animator))
+  }
 
-  pub unsafe fn draw(
-    &mut self,
-    gl: &Context,
-    camera: &Camera,
-    time: f32,
-    screen_width: u32,
-    screen_height: u32,
-  ) -> Result<()> {
-    // Update camera uniform block for all bound shaders
-    self.camera_ubo.upload(gl, &camera.uniform_block());
+  fn entities(&self) -> impl Iterator<Item = &Entity> {
+    iter::once(&self.floor)
+      .chain(self.cubes.iter())
+      .chain(iter::once(&self.exploder))
+      .chain(self.skinned.iter())
+  }
 
-    // Draw all lit objects
+  fn entities_mut(&mut self) -> impl Iterator<Item = &mut Entity> {
+    iter::once(&mut self.floor)
+      .chain(self.cubes.iter_mut())
+      .chain(iter::once(&mut self.exploder))
+      .chain(self.skinned.iter_mut())
+  }
+
+  pub unsafe fn bind_camera_block(&self, gl: &Context, shader: &Shader) {
+    shader
+      .activate(gl)
+      .bind_uniform(gl, "CameraBlock", &self.camera_ubo);
+  }
+
+  // Text drawn under the caption over everything else, e.g. stats
+  pub fn set_overlay(&mut self, overlay: Option<String>) {
+    self.overlay = overlay.map(|overlay| {
+      Text::new(
+        overlay,
+        "DejaVuSans",
+        18.,
+        [1., 1., 0.6, 1.],
+        glm::vec2(30., 90.),
+      )
+    });
+  }
+
+  // Shown in place of the overlay while any shader fails to rebuild
+  pub fn set_shader_errors(&mut self, errors: Option<&str>) {
+    self.shader_errors = errors.map(|errors| {
+      Text::new(
+        errors,
+        "DejaVuSans",
+        16.,
+        [1., 0.4, 0.4, 1.],
+        glm::vec2(30., 90.),
+      )
+    });
+  }
+
+  // Shown in place of the overlay for a moment, e.g. after saving a screenshot
+  pub fn set_notice(&mut self, notice: Option<&str>) {
+    self.notice = notice.map(|notice| {
+      Text::new(
+        notice,
+        "DejaVuSans",
+        16.,
+        [0.6, 1., 0.6, 1.],
+        glm::vec2(30., 90.),
+      )
+    });
+  }
+
+  pub fn skybox_texture(&self) -> &Texture<TCubemap> {
+    &self.skybox_texture
+  }
+
+  pub fn dir_lights(&self) -> &[DirLight] {
+    &self.dir_lights
+  }
+
+  pub fn spot_lights(&self) -> &[SpotLight] {
+    &self.spot_lights
+  }
+
+  // Redraw opaque entities for screen-space buffers like motion vectors and
+  // normals. Grass is transparent and the exploding model is displaced by its
+  // geometry shader, so neither would line up with the depth buffer.
+  pub unsafe fn draw_opaque(&self, gl: &Context, shader: &mut ActiveShader) {
+    let entities = iter::once(&self.floor)
+      .chain(self.cubes.iter())
+      .chain(self.skinned.iter());
+    for entity in entities {
+      entity.draw_with_history(gl, shader);
+    }
+  }
+
+  // Call once a frame has been drawn so the next one can compute motion
+  pub fn end_frame(&mut self) {
+    for entity in self.entities_mut() {
+      entity.end_frame();
+    }
+  }
+
+  // Redraw every entity with the current debug render mode's shader
+  unsafe fn draw_render_mode(&self, gl: &Context) {
+    let mut shader = match self.render_modes.begin(gl) {
+      Some(shader) => shader,
+      None => {
+        return;
+      }
+    };
+
+    // Materials are numbered by their diffuse texture in order of appearance
+    let mut materials = vec![];
+    for entity in self.entities() {
+      shader.bind_uniform(gl, "model", &entity.transform);
+      shader.bind_uniform(gl, "skinned", &entity.animator.is_some());
+      if let Some(animator) = entity.animator.as_ref() {
+        shader.bind_uniform(gl, "joint_matrices", animator.joint_matrices());
+      }
+
+      for mesh in entity.model.active_meshes() {
+        let material_id = match mesh.material.as_ref() {
+          Some(material) => {
+            let texture = material.diffuse.texture;
+            match materials.iter().position(|other| *other == texture) {
+              Some(i) => i as i32,
+              None => {
+                materials.push(texture);
+                (materials.len() - 1) as i32
+              }
+            }
+          }
+          None => -1,
+        };
+        shader.bind_uniform(gl, "material_id", &material_id);
+        mesh.draw(gl, &mut shader);
+      }
+    }
+
+    self.render_modes.end(gl);
+  }
+
+  pub fn update(&mut self, _elapsed: f32, dt: f32, camera: &Camera) {
+    for entity in self.entities_mut() {
+      entity.update(dt, camera);
+    }
+
+    // The procedural sky's clock moves the sun, the cubemap's sun is fixed
+    self.dir_lights[0] = match self.sky {
+      SkyMode::Procedural => {
+        self.time_of_day.update(dt);
+        self.time_of_day.sun_light()
+      }
+      SkyMode::Cubemap => self.cubemap_sun.clone(),
+    };
+
+    // Debug shapes are immediate mode, so re-queue everything each update
+    self.debug_draw.clear();
+    for light in self.point_lights.iter().chain(&self.gizmo_point_lights) {
+      self.debug_draw.point_light(light);
+    }
+    for light in &self.spot_lights {
+      self.debug_draw.spot_light(light, 3.);
+    }
+    for light in &self.dir_lights {
+      let start = glm::vec3(0., 4., 0.);
+      let end = start + glm::normalize(&light.direction);
+      self
+        .debug_draw
+        .arrow(start, end, glm::vec4(1., 1., 0., 1.), false);
+    }
+    let transforms = self
+      .entities()
+      .map(|entity| entity.transform)
+      .collect::<Vec<_>>();
+    for transform in transforms {
+      self.debug_draw.axes(&transform, 0.5, false);
+    }
+  }
+
+  // Opaque lit entities and terrain, drawn from the camera in the uniform block
+  unsafe fn draw_lit(&self, gl: &Context, camera: &Camera, time: f32) {
     let mut shader = self.light_shader.activate(gl);
     shader.bind_uniform(gl, "dir_lights", &self.dir_lights);
     shader.bind_uniform(gl, "spot_lights", &self.spot_lights);
@@ -273,32 +798,150 @@ impl Scene {
     self.exploder.draw(gl, &mut shader);
     shader.bind_uniform(gl, "should_explode", &false);
 
-    // Sort transparent objs in order of dist to camera so transparency works correctly
-    let mut grasses = self.grasses.iter().collect::<Vec<_>>();
-    grasses.sort_by_key(|grass| {
-      let translation = grass.transform.column_part(3, 3);
-      ordered_float::OrderedFloat(glm::length2(&(camera.pos - translation)))
-    });
-    for grass in grasses.into_iter().rev() {
-      grass.draw(gl, &mut shader);
+    // Draw terrain chunks in view, which blend their own materials
+    let mut shader = self.terrain.shader().activate(gl);
+    shader.bind_uniform(gl, "dir_lights", &self.dir_lights);
+    shader.bind_uniform(gl, "spot_lights", &self.spot_lights);
+    shader.bind_uniform(gl, "point_lights", &self.point_lights);
+    self
+      .terrain
+      .draw(gl, &mut shader, &camera.view_projection());
+
+    // Draw skinned objects, which need their own vertex shader
+    let mut shader = self.skinned_shader.activate(gl);
+    shader.bind_uniform(gl, "dir_lights", &self.dir_lights);
+    shader.bind_uniform(gl, "spot_lights", &self.spot_lights);
+    shader.bind_uniform(gl, "point_lights", &self.point_lights);
+    for entity in &self.skinned {
+      entity.draw(gl, &mut shader);
     }
+  }
 
-    // Draw cubemap skybox
-    let mut shader = self.skybox_shader.activate(gl);
-    shader.bind_uniform(gl, "skybox", &self.skybox_texture);
+  unsafe fn draw_skybox(&self, gl: &Context) {
+    let mut shader = match self.sky {
+      SkyMode::Cubemap => {
+        let mut shader = self.skybox_shader.activate(gl);
+        shader.bind_uniform(gl, "skybox", &self.skybox_texture);
+        shader
+      }
+      SkyMode::Procedural => self.procedural_sky.bind(gl, &self.time_of_day),
+    };
 
     // Have to disable face culling because we're viewing the inside of a cube
     gl.disable(glow::CULL_FACE);
     self.skybox.draw(gl, &mut shader);
     gl.enable(glow::CULL_FACE);
+  }
+
+  // Render the water's reflection and refraction with the opaque scene and
+  // sky. Leaves the default framebuffer bound and the viewport changed.
+  pub unsafe fn draw_water_passes(&self, gl: &Context, camera: &Camera, time: f32) {
+    self.water.draw_passes(gl, camera, |camera| {
+      self.camera_ubo.upload(gl, &camera.uniform_block());
+      self.draw_lit(gl, camera, time);
+      self.draw_skybox(gl);
+    });
+  }
+
+  pub unsafe fn draw(
+    &mut self,
+    gl: &Context,
+    camera: &Camera,
+    time: f32,
+    screen_width: u32,
+    screen_height: u32,
+    profiler: &mut Profiler,
+  ) -> Result<()> {
+    // Update camera uniform block for all bound shaders
+    self.camera_ubo.upload(gl, &camera.uniform_block());
+
+    // Step GPU particles and upload CPU particles before anything is drawn
+    let particle_renderer = &self.particle_renderer;
+    let entities = iter::once(&mut self.floor)
+      .chain(self.cubes.iter_mut())
+      .chain(iter::once(&mut self.exploder))
+      .chain(self.skinned.iter_mut());
+    for entity in entities {
+      for emitter in entity.emitters.iter_mut() {
+        particle_renderer.prepare(gl, emitter, time);
+      }
+    }
+
+    // Draw all lit objects
+    profiler.gpu_begin(gl, "opaque");
+    self.draw_lit(gl, camera, time);
+
+    // Water refracts everything drawn so far, and transparent objects may be in front of it
+    profiler.gpu_begin(gl, "water");
+    let mut shader = self.water.shader().activate(gl);
+    shader.bind_uniform(gl, "dir_lights", &self.dir_lights);
+    self.water.draw(gl, &mut shader, camera, time);
+
+    // Sort transparent objs in order of dist to camera so transparency works correctly.
+    // Particle emitters are sorted as a whole alongside the billboards.
+    enum Transparent<'a> {
+      Billboard(&'a Billboard),
+      Particles(&'a ParticleEmitter),
+    }
+    let billboards = self.billboards.iter().map(|billboard| {
+      (
+        glm::length2(&(camera.pos - billboard.position)),
+        Transparent::Billboard(billboard),
+      )
+    });
+    let emitters = self
+      .entities()
+      .flat_map(|entity| entity.emitters.iter())
+      .map(|emitter| {
+        (
+          glm::length2(&(camera.pos - emitter.origin())),
+          Transparent::Particles(emitter),
+        )
+      });
+    profiler.gpu_begin(gl, "transparent");
+    let mut transparent = billboards.chain(emitters).collect::<Vec<_>>();
+    transparent.sort_by_key(|(dist, _)| ordered_float::OrderedFloat(*dist));
+    for (_, object) in transparent.into_iter().rev() {
+      match object {
+        Transparent::Billboard(billboard) => {
+          let mut shader = self.billboard_renderer.shader().activate(gl);
+          shader.bind_uniform(gl, "dir_lights", &self.dir_lights);
+          self
+            .billboard_renderer
+            .draw(gl, &mut shader, billboard, time);
+        }
+        Transparent::Particles(emitter) => {
+          self.particle_renderer.draw(gl, emitter);
+        }
+      }
+    }
+
+    self.draw_render_mode(gl);
+
+    // Draw cubemap skybox
+    profiler.gpu_begin(gl, "skybox");
+    self.draw_skybox(gl);
+
+    // Draw queued debug shapes on top of the scene
+    profiler.gpu_begin(gl, "debug");
+    self.debug_draw.flush(gl);
 
     // Draw text, which queues draw commands on the individual fonts
+    profiler.gpu_begin(gl, "text");
     self.text.draw(&mut self.fonts);
+    if let Some(errors) = self.shader_errors.as_ref() {
+      errors.draw(&mut self.fonts);
+    } else if let Some(notice) = self.notice.as_ref() {
+      notice.draw(&mut self.fonts);
+    } else if let Some(overlay) = self.overlay.as_ref() {
+      overlay.draw(&mut self.fonts);
+    }
 
     // Flush each fonts draw commands
     for font in self.fonts.values_mut() {
       font.draw(gl, &self.text_shader, screen_width, screen_height)?;
     }
+    profiler.gpu_end(gl);
 
     Ok(())
   }
@@ -1,30 +1,65 @@
 use std::u32;
 
 use crate::{
+  camera::Camera,
   geometry::Geometry,
   mesh::Mesh,
+  post_process::PostStack,
   prelude::*,
   shader::{ActiveShader, Shader},
+  ssr::Ssr,
+  taa::Taa,
   texture::{Texture, TextureBuilder},
+  volumetrics::Volumetrics,
 };
 
-struct Framebuffer {
-  fbo: GlFramebuffer,
-  render_texture: Texture,
+pub struct Framebuffer {
+  pub fbo: GlFramebuffer,
+  pub render_texture: Texture,
+  pub depth_texture: Option<Texture>,
 }
 
 impl Framebuffer {
-  pub unsafe fn new(gl: &Context, width: u32, height: u32) -> Result<Self> {
-    // Framebuffer contains another render target (color/depth/stencil buffers + texture)
-    let fbo = gl.create_framebuffer().map_err(Error::msg)?;
-    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
-
-    // Render texture is a 2D image that contains output of rendering
+  pub unsafe fn new(gl: &Context, width: u32, height: u32, with_depth: bool) -> Result<Self> {
+    // Render texture is a 2D image that contains output of rendering.
+    // Clamp so that effects sampling neighbors don't wrap around the screen.
     let render_texture = TextureBuilder::new(gl)
-      .with_format(glow::RGB)
+      .with_format(glow::RGBA)
       .with_tex_parameter(glow::TEXTURE_MIN_FILTER, glow::LINEAR)
       .with_tex_parameter(glow::TEXTURE_MAG_FILTER, glow::LINEAR)
+      .with_tex_parameter(glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE)
+      .with_tex_parameter(glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE)
       .render_texture(width, height)?;
+
+    // Depth and stencil go in a texture so that post effects can read depth.
+    // Depth textures can't be linearly filtered on WebGL.
+    let depth_texture = if with_depth {
+      Some(
+        TextureBuilder::new(gl)
+          .with_format(glow::DEPTH_STENCIL)
+          .with_tex_parameter(glow::TEXTURE_MIN_FILTER, glow::NEAREST)
+          .with_tex_parameter(glow::TEXTURE_MAG_FILTER, glow::NEAREST)
+          .with_tex_parameter(glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE)
+          .with_tex_parameter(glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE)
+          .render_texture(width, height)?,
+      )
+    } else {
+      None
+    };
+
+    Self::from_textures(gl, render_texture, depth_texture)
+  }
+
+  // Attach existing textures, e.g. to share one depth buffer between framebuffers
+  pub unsafe fn from_textures(
+    gl: &Context,
+    render_texture: Texture,
+    depth_texture: Option<Texture>,
+  ) -> Result<Self> {
+    // Framebuffer contains another render target (color/depth/stencil buffers + texture)
+    let fbo = gl.create_framebuffer().map_err(Error::msg)?;
+    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+
     gl.framebuffer_texture_2d(
       glow::FRAMEBUFFER,
       glow::COLOR_ATTACHMENT0,
@@ -32,23 +67,15 @@ impl Framebuffer {
       Some(render_texture.texture),
       0,
     );
-
-    // We don't read from depth or stencil buffers so use a renderbuffer
-    let rbo = gl.create_renderbuffer().map_err(Error::msg)?;
-    gl.bind_renderbuffer(glow::RENDERBUFFER, Some(rbo));
-    gl.renderbuffer_storage(
-      glow::RENDERBUFFER,
-      glow::DEPTH24_STENCIL8,
-      width as i32,
-      height as i32,
-    );
-    gl.bind_renderbuffer(glow::RENDERBUFFER, None);
-    gl.framebuffer_renderbuffer(
-      glow::FRAMEBUFFER,
-      glow::DEPTH_STENCIL_ATTACHMENT,
-      glow::RENDERBUFFER,
-      Some(rbo),
-    );
+    if let Some(depth_texture) = depth_texture.as_ref() {
+      gl.framebuffer_texture_2d(
+        glow::FRAMEBUFFER,
+        glow::DEPTH_STENCIL_ATTACHMENT,
+        glow::TEXTURE_2D,
+        Some(depth_texture.texture),
+        0,
+      );
+    }
 
     // Fail if framebuffer isn't complete
     if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
@@ -60,19 +87,48 @@ impl Framebuffer {
     Ok(Framebuffer {
       fbo,
       render_texture,
+      depth_texture,
     })
   }
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DepthView {
+  Off,
+  Grayscale,
+  FalseColor,
+}
+
+impl DepthView {
+  pub fn next(self) -> Self {
+    match self {
+      DepthView::Off => DepthView::Grayscale,
+      DepthView::Grayscale => DepthView::FalseColor,
+      DepthView::FalseColor => DepthView::Off,
+    }
+  }
+}
+
 pub struct ScreenCapture {
   framebuffer: Framebuffer,
   screen_shader: Shader,
+  depth_shader: Shader,
   screen_geom: Mesh,
+
+  // Where the final pass is drawn, or the default framebuffer if None
+  output: Option<Framebuffer>,
 }
 
 impl ScreenCapture {
-  pub async unsafe fn new(gl: &Context, width: u32, height: u32) -> Result<Self> {
-    let framebuffer = Framebuffer::new(&gl, width, height)?;
+  // Offscreen captures draw their final pass into a framebuffer of their own
+  // instead of the screen, e.g. when there is no window to draw to
+  pub async unsafe fn new(gl: &Context, width: u32, height: u32, offscreen: bool) -> Result<Self> {
+    let framebuffer = Framebuffer::new(&gl, width, height, true)?;
+    let output = if offscreen {
+      Some(Framebuffer::new(&gl, width, height, false)?)
+    } else {
+      None
+    };
 
     let screen_geom = Geometry::Plane {
       length: 2.,
@@ -81,18 +137,27 @@ impl ScreenCapture {
     }
     .to_mesh(&gl, None)?;
 
-    let screen_shader = Shader::load(
-      &gl,
-      "assets/shaders/screen.vert",
-      "assets/shaders/screen.frag",
-      None,
-    )
-    .await?;
+    let (screen_shader, depth_shader) = try_join!(
+      Shader::load(
+        &gl,
+        "assets/shaders/screen.vert",
+        "assets/shaders/screen.frag",
+        None,
+      ),
+      Shader::load(
+        &gl,
+        "assets/shaders/screen.vert",
+        "assets/shaders/depth.frag",
+        None,
+      )
+    )?;
 
     Ok(ScreenCapture {
       screen_shader,
+      depth_shader,
       screen_geom,
       framebuffer,
+      output,
     })
   }
 
@@ -101,15 +166,77 @@ impl ScreenCapture {
     gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer.fbo));
   }
 
-  pub unsafe fn replay(&self, gl: &Context, init_shader: impl Fn(&Context, &mut ActiveShader)) {
+  // Add reflections to the recorded image, resolve TAA and run it through the
+  // post-processing chain, then copy the result onto the screen as the final pass
+  pub unsafe fn replay(
+    &self,
+    gl: &Context,
+    camera: &Camera,
+    post: &PostStack,
+    taa: &Taa,
+    ssr: &Ssr,
+    volumetrics: &Volumetrics,
+  ) {
+    gl.disable(glow::DEPTH_TEST);
+    let reflected = ssr.apply(
+      gl,
+      &self.screen_geom,
+      &self.framebuffer.render_texture,
+      camera,
+    );
+
+    // Before TAA, which smooths out the fog's dithering
+    let scattered = volumetrics.apply(gl, &self.screen_geom, reflected, camera);
+    let resolved = taa.resolve(gl, &self.screen_geom, scattered);
+    let output = post.apply(
+      gl,
+      &self.screen_geom,
+      resolved,
+      self.depth_texture(),
+      taa.velocity_texture(),
+    );
+    self.draw_to_screen(gl, &self.screen_shader, |gl, shader| {
+      shader.bind_uniform(gl, "screenTexture", output);
+    });
+  }
+
+  pub fn screen_geom(&self) -> &Mesh {
+    &self.screen_geom
+  }
+
+  // Framebuffer holding the final image, None being the default framebuffer
+  pub fn output_fbo(&self) -> Option<GlFramebuffer> {
+    self.output.as_ref().map(|output| output.fbo)
+  }
+
+  pub fn depth_texture(&self) -> &Texture {
+    self.framebuffer.depth_texture.as_ref().unwrap()
+  }
+
+  // Draw the recorded depth buffer, linearized using the camera's clip planes
+  pub unsafe fn replay_depth(&self, gl: &Context, camera: &Camera, view: DepthView) {
+    let (near, far) = camera.near_far();
+    self.draw_to_screen(gl, &self.depth_shader, |gl, shader| {
+      shader.bind_uniform(gl, "depthTexture", self.depth_texture());
+      shader.bind_uniform(gl, "near", &near);
+      shader.bind_uniform(gl, "far", &far);
+      shader.bind_uniform(gl, "false_color", &(view == DepthView::FalseColor));
+    });
+  }
+
+  unsafe fn draw_to_screen(
+    &self,
+    gl: &Context,
+    shader: &Shader,
+    init_shader: impl Fn(&Context, &mut ActiveShader),
+  ) {
     // Unbind the framebuffer and then draw the render texture onto the screen
-    gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+    gl.bind_framebuffer(glow::FRAMEBUFFER, self.output_fbo());
     gl.clear_color(1., 1., 1., 1.);
     gl.clear(glow::COLOR_BUFFER_BIT);
 
-    let mut shader = self.screen_shader.activate(&gl);
+    let mut shader = shader.activate(&gl);
     gl.disable(glow::DEPTH_TEST);
-    shader.bind_uniform(gl, "screenTexture", &self.framebuffer.render_texture);
     init_shader(gl, &mut shader);
     self.screen_geom.draw(&gl, &mut shader);
     gl.enable(glow::DEPTH_TEST);
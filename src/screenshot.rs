@@ -0,0 +1,219 @@
+use crate::{prelude::*, texture::Texture};
+use image::RgbaImage;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+  fs::File,
+  io::{BufWriter, Write},
+  path::{Path, PathBuf},
+};
+
+// Reads back the color attachment of a framebuffer (None for the default one)
+pub unsafe fn read_framebuffer(
+  gl: &Context,
+  fbo: Option<GlFramebuffer>,
+  width: u32,
+  height: u32,
+) -> RgbaImage {
+  let mut pixels = vec![0u8; (width * height * 4) as usize];
+  gl.bind_framebuffer(glow::FRAMEBUFFER, fbo);
+  gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+  gl.read_pixels(
+    0,
+    0,
+    width as i32,
+    height as i32,
+    glow::RGBA,
+    glow::UNSIGNED_BYTE,
+    glow::PixelPackData::Slice(&mut pixels),
+  );
+  gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+
+  // OpenGL's rows start at the bottom of the image, image's at the top
+  let image = RgbaImage::from_raw(width, height, pixels).unwrap();
+  image::imageops::flip_vertical(&image)
+}
+
+// Reads back a color texture by attaching it to a temporary framebuffer.
+// Textures don't know their own size, so it has to be passed in. Depth
+// textures can't be read this way, and GLES can't convert float textures.
+pub unsafe fn read_texture(
+  gl: &Context,
+  texture: &Texture,
+  width: u32,
+  height: u32,
+) -> Result<RgbaImage> {
+  let fbo = gl.create_framebuffer().map_err(Error::msg)?;
+  gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+  gl.framebuffer_texture_2d(
+    glow::FRAMEBUFFER,
+    glow::COLOR_ATTACHMENT0,
+    glow::TEXTURE_2D,
+    Some(texture.texture),
+    0,
+  );
+  let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+
+  let image = if status == glow::FRAMEBUFFER_COMPLETE {
+    Some(read_framebuffer(gl, Some(fbo), width, height))
+  } else {
+    None
+  };
+  gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+  gl.delete_framebuffer(fbo);
+  image.context("Texture can't be attached as a color buffer")
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_png(image: &RgbaImage, path: impl AsRef<Path>) -> Result<()> {
+  let path = path.as_ref();
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir)?;
+  }
+  image
+    .save_with_format(path, image::ImageFormat::Png)
+    .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// First path of the form {dir}/{prefix}_NNNN{extension} that doesn't exist yet
+#[cfg(not(target_arch = "wasm32"))]
+pub fn next_path(dir: &str, prefix: &str, extension: &str) -> PathBuf {
+  (0..)
+    .map(|i| Path::new(dir).join(format!("{}_{:04}{}", prefix, i, extension)))
+    .find(|path| !path.exists())
+    .unwrap()
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RecordFormat {
+  // Numbered PNGs in a directory
+  Png,
+
+  // One uncompressed YUV 4:4:4 stream, which ffmpeg and most players read
+  Y4m,
+}
+
+// Captures consecutive frames while driving a fixed simulated timestep, so
+// clips play back smoothly however long each frame took to render and save
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Recorder {
+  format: RecordFormat,
+  path: PathBuf,
+  y4m: Option<BufWriter<File>>,
+  width: u32,
+  height: u32,
+  fps: u32,
+  frame: u32,
+  start_time: f32,
+
+  // Whether the simulation still has to advance past the last captured frame
+  step_pending: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Recorder {
+  // path is a directory for PNG sequences and a file for Y4M
+  pub fn start(
+    path: impl Into<PathBuf>,
+    format: RecordFormat,
+    width: u32,
+    height: u32,
+    fps: u32,
+    start_time: f32,
+  ) -> Result<Self> {
+    let path = path.into();
+    let y4m = match format {
+      RecordFormat::Png => {
+        std::fs::create_dir_all(&path)?;
+        None
+      }
+      RecordFormat::Y4m => {
+        if let Some(dir) = path.parent() {
+          std::fs::create_dir_all(dir)?;
+        }
+        let file =
+          File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(
+          writer,
+          "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+          width, height, fps
+        )?;
+        Some(writer)
+      }
+    };
+
+    Ok(Recorder {
+      format,
+      path,
+      y4m,
+      width,
+      height,
+      fps,
+      frame: 0,
+      start_time,
+      step_pending: false,
+    })
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  pub fn frames(&self) -> u32 {
+    self.frame
+  }
+
+  // Simulated time of the next frame to be captured
+  pub fn time(&self) -> f32 {
+    self.start_time + self.frame as f32 / self.fps as f32
+  }
+
+  // Time to advance the simulation by: one frame's worth after each capture,
+  // and nothing otherwise
+  pub fn take_dt(&mut self) -> f32 {
+    if self.step_pending {
+      self.step_pending = false;
+      1. / self.fps as f32
+    } else {
+      0.
+    }
+  }
+
+  pub unsafe fn capture(&mut self, gl: &Context, fbo: Option<GlFramebuffer>) -> Result<()> {
+    let image = read_framebuffer(gl, fbo, self.width, self.height);
+    match self.y4m.as_mut() {
+      None => save_png(&image, self.path.join(format!("{:06}.png", self.frame)))?,
+      Some(writer) => {
+        writer.write_all(b"FRAME\n")?;
+        writer.write_all(&rgb_to_yuv444(&image))?;
+      }
+    }
+    self.frame += 1;
+    self.step_pending = true;
+    Ok(())
+  }
+
+  pub fn finish(mut self) -> Result<()> {
+    if let Some(writer) = self.y4m.as_mut() {
+      writer.flush()?;
+    }
+    Ok(())
+  }
+}
+
+// Planar Y, U then V using BT.601 limited range, the Y4M default
+#[cfg(not(target_arch = "wasm32"))]
+fn rgb_to_yuv444(image: &RgbaImage) -> Vec<u8> {
+  let num_pixels = (image.width() * image.height()) as usize;
+  let mut planes = vec![0u8; num_pixels * 3];
+  for (i, pixel) in image.pixels().enumerate() {
+    let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+    let y = 16. + 0.257 * r + 0.504 * g + 0.098 * b;
+    let u = 128. - 0.148 * r - 0.291 * g + 0.439 * b;
+    let v = 128. + 0.439 * r - 0.368 * g - 0.071 * b;
+    planes[i] = y.round() as u8;
+    planes[num_pixels + i] = u.round() as u8;
+    planes[num_pixels * 2 + i] = v.round() as u8;
+  }
+  planes
+}
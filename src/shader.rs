@@ -3,46 +3,235 @@ use na::{
   dimension::{U1, U3, U4},
   storage::Storage,
 };
-use std::{marker::PhantomData, mem::size_of, path::Path, slice};
+use std::{
+  cell::{Cell, RefCell},
+  fs, iter,
+  marker::PhantomData,
+  mem::{self, size_of},
+  path::{Path, PathBuf},
+  rc::{Rc, Weak},
+  slice,
+  time::SystemTime,
+};
 use std140::ReprStd140;
 
-use crate::{io, prelude::*};
+use crate::{
+  prelude::*,
+  preprocessor::{self, ShaderSource},
+  reflection::{Reflection, INT_TYPES},
+  stats,
+};
+
+// Files a program is built from, kept to rebuild it when they change
+struct ShaderPaths {
+  vertex: PathBuf,
 
-pub struct Shader {
-  id: GlProgram,
+  // None for transform feedback, which only needs a placeholder
+  fragment: Option<PathBuf>,
+  geometry: Option<PathBuf>,
+  varyings: Vec<String>,
 }
 
-impl Shader {
-  pub async unsafe fn load(
-    gl: &Context,
-    vertex_path: impl AsRef<Path>,
-    fragment_path: impl AsRef<Path>,
-    geometry_path: Option<&Path>,
-  ) -> Result<Self> {
-    let vertex_path = vertex_path.as_ref();
+impl ShaderPaths {
+  // Returns the program, its uniforms and every file that went into it,
+  // includes too
+  async unsafe fn build(&self, gl: &Context) -> Result<(GlProgram, Reflection, Vec<WatchedFile>)> {
     let (vertex_source, fragment_source, geometry_source) = try_join!(
-      io::load_string(vertex_path),
-      io::load_string(fragment_path),
+      preprocessor::load_source(&self.vertex),
+      async {
+        match self.fragment.as_ref() {
+          Some(path) => preprocessor::load_source(path).await,
+
+          // GLES requires a fragment shader even when rasterization is discarded
+          None => Ok(ShaderSource::inline(
+            "<transform feedback fragment>",
+            "out vec4 FragColor;\nvoid main() { FragColor = vec4(0.); }\n".to_owned(),
+          )),
+        }
+      },
       async {
-        match geometry_path {
+        match self.geometry.as_ref() {
           Some(path) => {
-            let bytes = io::load_string(path).await;
-            bytes.map(|bytes| Some(bytes))
+            let source = preprocessor::load_source(path).await;
+            source.map(Some)
           }
           None => Ok(None),
         }
       }
     )?;
-    Self::new(gl, vertex_source, fragment_source, geometry_source)
-      .context(format!("With shader path {:?}", vertex_path))
+
+    let files = iter::once(&vertex_source)
+      .chain(iter::once(&fragment_source))
+      .chain(geometry_source.as_ref())
+      .flat_map(ShaderSource::file_names)
+      .map(|name| WatchedFile::new(PathBuf::from(name)))
+      .collect();
+    let varyings = self.varyings.iter().map(String::as_str).collect::<Vec<_>>();
+    let (program, reflection) = Shader::build(
+      gl,
+      vertex_source,
+      fragment_source,
+      geometry_source,
+      &varyings,
+    )?;
+    Ok((program, reflection, files))
+  }
+}
+
+struct WatchedFile {
+  path: PathBuf,
+  modified: Option<SystemTime>,
+}
+
+impl WatchedFile {
+  fn new(path: PathBuf) -> Self {
+    let modified = Self::modified_time(&path);
+    WatchedFile { path, modified }
+  }
+
+  // None if there's no such file, like for inline sources and on the web
+  fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+      .and_then(|metadata| metadata.modified())
+      .ok()
+  }
+
+  // Also takes the new modification time
+  fn changed(&mut self) -> bool {
+    let modified = Self::modified_time(&self.path);
+    modified != mem::replace(&mut self.modified, modified)
+  }
+}
+
+// The GL program behind a Shader, shared with the list of live programs so
+// that it can be swapped for a rebuilt one when its files change
+struct Program {
+  id: Cell<GlProgram>,
+  reflection: RefCell<Rc<Reflection>>,
+  paths: ShaderPaths,
+  files: RefCell<Vec<WatchedFile>>,
+
+  // Uniform block bindings, made again on a rebuilt program
+  blocks: RefCell<Vec<(String, u32)>>,
+
+  // Why the last rebuild failed, if the old program is still in use
+  error: RefCell<Option<String>>,
+}
+
+impl Program {
+  async unsafe fn reload(&self, gl: &Context) {
+    match self.paths.build(gl).await {
+      Ok((id, reflection, files)) => {
+        for (name, binding) in self.blocks.borrow().iter() {
+          if let Some(index) = gl.get_uniform_block_index(id, name) {
+            gl.uniform_block_binding(id, index, *binding);
+          }
+        }
+        gl.delete_program(self.id.replace(id));
+        *self.reflection.borrow_mut() = Rc::new(reflection);
+        *self.files.borrow_mut() = files;
+        *self.error.borrow_mut() = None;
+      }
+      Err(err) => *self.error.borrow_mut() = Some(format!("{:?}", err)),
+    }
+  }
+}
+
+thread_local! {
+  static PROGRAMS: RefCell<Vec<Weak<Program>>> = RefCell::new(vec![]);
+}
+
+// Rebuilds every live shader with a file that changed since the last call.
+// Shaders that fail to build keep their old program. Returns the errors of
+// all shaders whose last rebuild failed.
+pub async unsafe fn reload_changed(gl: &Context) -> Vec<String> {
+  let programs = PROGRAMS.with(|programs| {
+    let mut programs = programs.borrow_mut();
+    programs.retain(|program| program.strong_count() > 0);
+    programs
+      .iter()
+      .filter_map(Weak::upgrade)
+      .collect::<Vec<_>>()
+  });
+
+  let mut errors = vec![];
+  for program in programs {
+    // Check every file, so each takes its new modification time
+    let changed = program
+      .files
+      .borrow_mut()
+      .iter_mut()
+      .fold(false, |changed, file| file.changed() || changed);
+    if changed {
+      program.reload(gl).await;
+    }
+    errors.extend(program.error.borrow().clone());
   }
+  errors
+}
 
-  pub unsafe fn new(
+pub struct Shader {
+  program: Rc<Program>,
+}
+
+impl Shader {
+  pub async unsafe fn load(
     gl: &Context,
-    mut vertex_source: String,
-    mut fragment_source: String,
-    mut geometry_source: Option<String>,
+    vertex_path: impl AsRef<Path>,
+    fragment_path: impl AsRef<Path>,
+    geometry_path: Option<&Path>,
   ) -> Result<Self> {
+    Self::from_paths(
+      gl,
+      ShaderPaths {
+        vertex: vertex_path.as_ref().to_owned(),
+        fragment: Some(fragment_path.as_ref().to_owned()),
+        geometry: geometry_path.map(Path::to_owned),
+        varyings: vec![],
+      },
+    )
+    .await
+  }
+
+  // Loads a vertex-only program whose outputs are captured by transform feedback
+  pub async unsafe fn load_transform_feedback(
+    gl: &Context,
+    vertex_path: impl AsRef<Path>,
+    varyings: &[&str],
+  ) -> Result<Self> {
+    Self::from_paths(
+      gl,
+      ShaderPaths {
+        vertex: vertex_path.as_ref().to_owned(),
+        fragment: None,
+        geometry: None,
+        varyings: varyings.iter().map(|varying| varying.to_string()).collect(),
+      },
+    )
+    .await
+  }
+
+  async unsafe fn from_paths(gl: &Context, paths: ShaderPaths) -> Result<Self> {
+    let (id, reflection, files) = paths.build(gl).await?;
+    let program = Rc::new(Program {
+      id: Cell::new(id),
+      reflection: RefCell::new(Rc::new(reflection)),
+      paths,
+      files: RefCell::new(files),
+      blocks: RefCell::new(vec![]),
+      error: RefCell::new(None),
+    });
+    PROGRAMS.with(|programs| programs.borrow_mut().push(Rc::downgrade(&program)));
+    Ok(Shader { program })
+  }
+
+  unsafe fn build(
+    gl: &Context,
+    mut vertex_source: ShaderSource,
+    mut fragment_source: ShaderSource,
+    mut geometry_source: Option<ShaderSource>,
+    varyings: &[&str],
+  ) -> Result<(GlProgram, Reflection)> {
     // Add directives needed for each platform
     let header = if cfg!(target_arch = "wasm32") {
       "#version 300 es\nprecision highp float;\n#define WASM\n"
@@ -57,87 +246,131 @@ impl Shader {
       crate::light::PointLight::TYPE_DEF,
       crate::light::DirLight::TYPE_DEF,
       crate::light::SpotLight::TYPE_DEF,
+      crate::water::Wave::TYPE_DEF,
     ]
     .join("\n");
 
-    let preprocess = |source| format!("{}\n{}\n{}", header, defs, source);
-
-    vertex_source = preprocess(vertex_source);
-    fragment_source = preprocess(fragment_source);
-    geometry_source = geometry_source.map(|s| preprocess(s));
-
-    // Compile individual shaders into OpenGL objects
-    let vertex_shader = Self::build_shader(&gl, glow::VERTEX_SHADER, &vertex_source)?;
-    let fragment_shader = Self::build_shader(&gl, glow::FRAGMENT_SHADER, &fragment_source)?;
-    let geometry_shader = geometry_source
-      .map(|s| Self::build_shader(&gl, glow::GEOMETRY_SHADER, &s))
-      .transpose()?;
+    // Sources start with #line directives, so errors past the preamble
+    // report the original file and line
+    let preamble = format!("{}\n{}\n", header, defs);
+
+    // Compile individual shaders into OpenGL objects, deleting the ones
+    // already compiled if one fails
+    let stages = iter::once((glow::VERTEX_SHADER, &mut vertex_source))
+      .chain(iter::once((glow::FRAGMENT_SHADER, &mut fragment_source)))
+      .chain(
+        geometry_source
+          .as_mut()
+          .map(|source| (glow::GEOMETRY_SHADER, source)),
+      );
+    let mut shaders = vec![];
+    for (shader_type, source) in stages {
+      match Self::build_shader(gl, shader_type, source, &preamble) {
+        Ok(shader) => shaders.push(shader),
+        Err(err) => {
+          for shader in shaders {
+            gl.delete_shader(shader);
+          }
+          return Err(err);
+        }
+      }
+    }
 
     // Link shaders into a single program
     let shader_program = gl.create_program().unwrap();
-    gl.attach_shader(shader_program, vertex_shader);
-    gl.attach_shader(shader_program, fragment_shader);
-    if let Some(geometry_shader) = geometry_shader {
-      gl.attach_shader(shader_program, geometry_shader);
+    for &shader in &shaders {
+      gl.attach_shader(shader_program, shader);
     }
 
-    gl.link_program(shader_program);
-    if !gl.get_program_link_status(shader_program) {
-      bail!(
-        "Shader program failed to link with error: {}",
-        gl.get_program_info_log(shader_program)
-      );
+    // Varyings have to be declared before linking
+    if !varyings.is_empty() {
+      gl.transform_feedback_varyings(shader_program, varyings, glow::INTERLEAVED_ATTRIBS);
     }
 
+    gl.link_program(shader_program);
+    let linked = gl.get_program_link_status(shader_program);
+
     // Cleanup shaders after linking
-    gl.delete_shader(vertex_shader);
-    gl.delete_shader(fragment_shader);
-    if let Some(geometry_shader) = geometry_shader {
-      gl.delete_shader(geometry_shader);
+    for shader in shaders {
+      gl.delete_shader(shader);
+    }
+
+    let mut names = vec![vertex_source.name(), fragment_source.name()];
+    names.extend(geometry_source.as_ref().map(ShaderSource::name));
+    let name = names.join(", ");
+    if !linked {
+      let log = gl.get_program_info_log(shader_program);
+      gl.delete_program(shader_program);
+      bail!("Shader program {} failed to link with error: {}", name, log);
     }
 
-    Ok(Shader { id: shader_program })
+    // Uniforms declared in any stage can be bound, used or not
+    let declared = iter::once(&vertex_source)
+      .chain(iter::once(&fragment_source))
+      .chain(geometry_source.as_ref())
+      .flat_map(ShaderSource::declared_uniforms)
+      .map(str::to_owned)
+      .collect();
+    let reflection = Reflection::new(gl, shader_program, name, declared);
+
+    Ok((shader_program, reflection))
   }
 
-  unsafe fn build_shader(gl: &Context, shader_type: u32, source: &str) -> Result<GlShader> {
+  unsafe fn build_shader(
+    gl: &Context,
+    shader_type: u32,
+    source: &mut ShaderSource,
+    preamble: &str,
+  ) -> Result<GlShader> {
     // Create a new OpenGL shader object
     let shader = gl.create_shader(shader_type).unwrap();
 
     // Pass source to OpenGL
-    gl.shader_source(shader, source);
+    gl.shader_source(shader, &source.with_preamble(preamble.to_owned()));
 
     // Call the OpenGL shader compiler
     gl.compile_shader(shader);
     if !gl.get_shader_compile_status(shader) {
+      let log = source.annotate(&gl.get_shader_info_log(shader));
+      gl.delete_shader(shader);
       bail!(
-        "{} shader failed to compile with error: {}",
+        "{} shader {} failed to compile with error:\n{}",
         match shader_type {
           glow::VERTEX_SHADER => "Vertex",
           glow::FRAGMENT_SHADER => "Fragment",
-          _ => "???",
+          glow::GEOMETRY_SHADER => "Geometry",
+          _ => "Unknown",
         },
-        gl.get_shader_info_log(shader)
+        source.name(),
+        log
       );
     }
 
     Ok(shader)
   }
 
-  unsafe fn location(&self, gl: &Context, name: &str) -> Option<GlUniformLocation> {
-    gl.get_uniform_location(self.id, name)
+  // Remembered so a rebuilt program gets the same binding
+  fn record_block(&self, name: &str, binding: u32) {
+    let mut blocks = self.program.blocks.borrow_mut();
+    match blocks.iter_mut().find(|(block, _)| block == name) {
+      Some(block) => block.1 = binding,
+      None => blocks.push((name.to_owned(), binding)),
+    }
   }
 
-  unsafe fn block_location(&self, gl: &Context, name: &str) -> Option<u32> {
-    gl.get_uniform_block_index(self.id, name)
+  fn program(&self) -> GlProgram {
+    self.program.id.get()
   }
 
-  fn program(&self) -> GlProgram {
-    self.id
+  // Active uniforms and blocks of the current program
+  pub fn reflection(&self) -> Rc<Reflection> {
+    self.program.reflection.borrow().clone()
   }
 
   // I wanted to call this "use" but that's a Rust keyword :'(
   pub unsafe fn activate(&self, gl: &Context) -> ActiveShader {
-    gl.use_program(Some(self.id));
+    gl.use_program(Some(self.program()));
+    stats::record_program(self.program());
     ActiveShader::new(self)
   }
 }
@@ -148,11 +381,13 @@ pub trait ShaderTypeDef {
 }
 
 pub trait ShaderBlockDef {
+  const BLOCK_NAME: &'static str;
   const BLOCK_DEF: &'static str;
 }
 
 pub struct ActiveShader<'a> {
   shader: &'a Shader,
+  reflection: Rc<Reflection>,
   num_textures: u32,
 }
 
@@ -161,6 +396,7 @@ impl<'a> ActiveShader<'a> {
   pub fn new(shader: &'a Shader) -> Self {
     ActiveShader {
       shader,
+      reflection: shader.reflection(),
       num_textures: 0,
     }
   }
@@ -179,12 +415,39 @@ impl<'a> ActiveShader<'a> {
     value.bind_uniform(gl, self, name);
   }
 
-  pub unsafe fn location(&self, gl: &Context, name: &str) -> Option<GlUniformLocation> {
-    self.shader.location(gl, name)
+  // Binds value only if the shader has the uniform, without warning if it
+  // doesn't. For values only some of the shaders they're bound to use, like a
+  // mesh's material when drawing depth or motion vectors.
+  pub unsafe fn bind_optional_uniform<T: BindUniform>(
+    &mut self,
+    gl: &Context,
+    name: &str,
+    value: &T,
+  ) {
+    if self.reflection.has_uniform(name) {
+      value.bind_uniform(gl, self, name);
+    }
   }
 
-  pub unsafe fn block_location(&self, gl: &Context, name: &str) -> Option<u32> {
-    self.shader.block_location(gl, name)
+  // Every BindUniform impl that uploads a value looks up its location here,
+  // giving the GL types it can upload
+  pub fn location(&self, name: &str, types: &[u32]) -> Option<&GlUniformLocation> {
+    let location = self.reflection.location(name, types);
+    if location.is_some() {
+      stats::record_uniform();
+    }
+    location
+  }
+
+  pub fn block_location(&self, name: &str) -> Option<u32> {
+    self.reflection.block_location(name)
+  }
+
+  pub unsafe fn bind_block(&self, gl: &Context, name: &str, binding: u32) {
+    if let Some(index) = self.block_location(name) {
+      gl.uniform_block_binding(self.program(), index, binding);
+    }
+    self.shader.record_block(name, binding);
   }
 
   pub fn reset_textures(&mut self) {
@@ -200,7 +463,8 @@ pub trait BindUniform {
 
 impl<T: BindUniform> BindUniform for Vec<T> {
   unsafe fn bind_uniform(&self, gl: &Context, shader: &mut ActiveShader, name: &str) {
-    shader.bind_uniform(gl, &format!("{}_len", name), &(self.len() as i32));
+    // Shaders that always loop over the whole array don't declare a length
+    shader.bind_optional_uniform(gl, &format!("{}_len", name), &(self.len() as i32));
     for (i, value) in self.iter().enumerate() {
       shader.bind_uniform(gl, &format!("{}[{}]", name, i), value);
     }
@@ -216,7 +480,7 @@ impl<T: BindUniform> BindUniform for &T {
 impl BindUniform for bool {
   unsafe fn bind_uniform(&self, gl: &Context, shader: &mut ActiveShader, name: &str) {
     gl.uniform_1_i32(
-      shader.location(gl, name).as_ref(),
+      shader.location(name, &[glow::BOOL]),
       if *self { 1 } else { 0 },
     );
   }
@@ -225,7 +489,7 @@ impl BindUniform for bool {
 impl BindUniform for [f32; 4] {
   unsafe fn bind_uniform(&self, gl: &Context, shader: &mut ActiveShader, name: &str) {
     gl.uniform_4_f32(
-      shader.location(gl, name).as_ref(),
+      shader.location(name, &[glow::FLOAT_VEC4]),
       self[0],
       self[1],
       self[2],
@@ -236,37 +500,68 @@ impl BindUniform for [f32; 4] {
 
 impl BindUniform for i32 {
   unsafe fn bind_uniform(&self, gl: &Context, shader: &mut ActiveShader, name: &str) {
-    gl.uniform_1_i32(shader.location(gl, name).as_ref(), *self);
+    gl.uniform_1_i32(shader.location(name, INT_TYPES), *self);
   }
 }
 
 impl BindUniform for f32 {
   unsafe fn bind_uniform(&self, gl: &Context, shader: &mut ActiveShader, name: &str) {
-    gl.uniform_1_f32(shader.location(gl, name).as_ref(), *self);
+    gl.uniform_1_f32(shader.location(name, &[glow::FLOAT]), *self);
   }
 }
 
 impl BindUniform for u32 {
   unsafe fn bind_uniform(&self, gl: &Context, shader: &mut ActiveShader, name: &str) {
-    gl.uniform_1_u32(shader.location(gl, name).as_ref(), *self);
+    gl.uniform_1_u32(shader.location(name, &[glow::UNSIGNED_INT]), *self);
+  }
+}
+
+impl BindUniform for Vec2 {
+  unsafe fn bind_uniform(&self, gl: &Context, shader: &mut ActiveShader, name: &str) {
+    gl.uniform_2_f32(shader.location(name, &[glow::FLOAT_VEC2]), self.x, self.y);
   }
 }
 
 impl BindUniform for Vec3 {
   unsafe fn bind_uniform(&self, gl: &Context, shader: &mut ActiveShader, name: &str) {
-    gl.uniform_3_f32(shader.location(gl, name).as_ref(), self.x, self.y, self.z);
+    gl.uniform_3_f32(
+      shader.location(name, &[glow::FLOAT_VEC3]),
+      self.x,
+      self.y,
+      self.z,
+    );
+  }
+}
+
+impl BindUniform for Vec4 {
+  unsafe fn bind_uniform(&self, gl: &Context, shader: &mut ActiveShader, name: &str) {
+    gl.uniform_4_f32(
+      shader.location(name, &[glow::FLOAT_VEC4]),
+      self.x,
+      self.y,
+      self.z,
+      self.w,
+    );
   }
 }
 
 impl BindUniform for Mat3 {
   unsafe fn bind_uniform(&self, gl: &Context, shader: &mut ActiveShader, name: &str) {
-    gl.uniform_matrix_3_f32_slice(shader.location(gl, name).as_ref(), false, self.as_slice());
+    gl.uniform_matrix_3_f32_slice(
+      shader.location(name, &[glow::FLOAT_MAT3]),
+      false,
+      self.as_slice(),
+    );
   }
 }
 
 impl BindUniform for Mat4 {
   unsafe fn bind_uniform(&self, gl: &Context, shader: &mut ActiveShader, name: &str) {
-    gl.uniform_matrix_4_f32_slice(shader.location(gl, name).as_ref(), false, self.as_slice());
+    gl.uniform_matrix_4_f32_slice(
+      shader.location(name, &[glow::FLOAT_MAT4]),
+      false,
+      self.as_slice(),
+    );
   }
 }
 
@@ -316,11 +611,7 @@ impl<T: ReprStd140> UniformBlock<T> {
 
 impl<T: ReprStd140> BindUniform for UniformBlock<T> {
   unsafe fn bind_uniform(&self, gl: &Context, shader: &mut ActiveShader, name: &str) {
-    gl.uniform_block_binding(
-      shader.program(),
-      shader.block_location(gl, name).unwrap(),
-      self.binding,
-    );
+    shader.bind_block(gl, name, self.binding);
   }
 }
 
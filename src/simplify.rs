@@ -0,0 +1,234 @@
+use crate::{mesh::Vertex, prelude::*};
+use ordered_float::OrderedFloat;
+use std::{
+  cmp::Reverse,
+  collections::{BinaryHeap, HashMap},
+  mem,
+  ops::{Add, AddAssign},
+};
+
+// Weight of the planes that pin open edges relative to the surface planes.
+// Vertices split along UV or normal seams make seams open edges too.
+const BORDER_WEIGHT: f64 = 100.;
+
+// Symmetric 4x4 error matrix summing squared distances to a set of planes,
+// stored as its upper triangle row by row
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+  // Squared distance to the plane n.p + d = 0, scaled by weight
+  fn plane(n: &Vec3, d: f32, weight: f64) -> Self {
+    let (a, b, c, d) = (n.x as f64, n.y as f64, n.z as f64, d as f64);
+    let w = weight;
+    Quadric([
+      a * a * w,
+      a * b * w,
+      a * c * w,
+      a * d * w,
+      b * b * w,
+      b * c * w,
+      b * d * w,
+      c * c * w,
+      c * d * w,
+      d * d * w,
+    ])
+  }
+
+  fn error(&self, p: &Vec3) -> f64 {
+    let q = &self.0;
+    let (x, y, z) = (p.x as f64, p.y as f64, p.z as f64);
+    q[0] * x * x
+      + 2. * q[1] * x * y
+      + 2. * q[2] * x * z
+      + 2. * q[3] * x
+      + q[4] * y * y
+      + 2. * q[5] * y * z
+      + 2. * q[6] * y
+      + q[7] * z * z
+      + 2. * q[8] * z
+      + q[9]
+  }
+}
+
+impl Add for Quadric {
+  type Output = Quadric;
+
+  fn add(mut self, other: Quadric) -> Quadric {
+    self += other;
+    self
+  }
+}
+
+impl AddAssign for Quadric {
+  fn add_assign(&mut self, other: Quadric) {
+    for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+      *a += b;
+    }
+  }
+}
+
+fn face_normal(positions: &[Vec3], tri: &[usize; 3]) -> Vec3 {
+  let (a, b, c) = (positions[tri[0]], positions[tri[1]], positions[tri[2]]);
+  (b - a).cross(&(c - a))
+}
+
+// Whether moving u onto v would turn any of u's other triangles over
+fn collapse_flips(
+  positions: &[Vec3],
+  triangles: &[[usize; 3]],
+  alive: &[bool],
+  faces: &[usize],
+  u: usize,
+  v: usize,
+) -> bool {
+  faces
+    .iter()
+    .filter(|&&f| alive[f])
+    .map(|&f| &triangles[f])
+    .filter(|tri| !tri.contains(&v))
+    .any(|tri| {
+      let before = face_normal(positions, tri);
+      let mut moved = *tri;
+      for i in moved.iter_mut() {
+        if *i == u {
+          *i = v;
+        }
+      }
+      before.dot(&face_normal(positions, &moved)) < 0.
+    })
+}
+
+// Reduces a triangle list to about target_triangles triangles by repeatedly
+// collapsing the edge whose removal adds the least quadric error (Garland and
+// Heckbert). Collapses move one endpoint onto the other, so every remaining
+// vertex keeps its original attributes and the returned indices still point
+// into vertices.
+pub fn simplify(vertices: &[Vertex], indices: &[u32], target_triangles: usize) -> Vec<u32> {
+  let positions = vertices.iter().map(|v| v.position).collect::<Vec<_>>();
+  let mut triangles = indices
+    .chunks(3)
+    .map(|t| [t[0] as usize, t[1] as usize, t[2] as usize])
+    .collect::<Vec<_>>();
+  let mut alive = vec![true; triangles.len()];
+  let mut num_alive = triangles.len();
+
+  let mut quadrics = vec![Quadric::default(); vertices.len()];
+  let mut vertex_faces = vec![vec![]; vertices.len()];
+  let mut edge_faces = HashMap::new();
+  for (f, tri) in triangles.iter().enumerate() {
+    // Area-weighted so that slivers don't dominate
+    let normal = face_normal(&positions, tri);
+    let area = normal.norm();
+    if area > 0. {
+      let n = normal / area;
+      let q = Quadric::plane(&n, -n.dot(&positions[tri[0]]), area as f64 / 2.);
+      for &v in tri {
+        quadrics[v] += q;
+      }
+    }
+
+    for i in 0..3 {
+      vertex_faces[tri[i]].push(f);
+      let (u, v) = (tri[i], tri[(i + 1) % 3]);
+      *edge_faces.entry((u.min(v), u.max(v))).or_insert(0) += 1;
+    }
+  }
+
+  // Open edges get a plane through them perpendicular to their face, so
+  // collapses can't pull silhouettes and seams out of place
+  for tri in &triangles {
+    let normal = face_normal(&positions, tri);
+    for i in 0..3 {
+      let (u, v) = (tri[i], tri[(i + 1) % 3]);
+      if edge_faces[&(u.min(v), u.max(v))] != 1 {
+        continue;
+      }
+      let edge = positions[v] - positions[u];
+      let n = edge.cross(&normal);
+      if n.norm() == 0. {
+        continue;
+      }
+      let n = n.normalize();
+      let q = Quadric::plane(
+        &n,
+        -n.dot(&positions[u]),
+        BORDER_WEIGHT * edge.norm_squared() as f64,
+      );
+      quadrics[u] += q;
+      quadrics[v] += q;
+    }
+  }
+
+  // Candidate collapses of u onto v, cheapest first. Entries are invalidated
+  // lazily by bumping the version of any vertex whose quadric changes.
+  let mut version = vec![0u32; vertices.len()];
+  let mut heap = BinaryHeap::new();
+  let push =
+    |heap: &mut BinaryHeap<_>, quadrics: &[Quadric], version: &[u32], u: usize, v: usize| {
+      let cost = (quadrics[u] + quadrics[v]).error(&positions[v]);
+      heap.push((Reverse(OrderedFloat(cost)), u, v, version[u], version[v]));
+    };
+  for &(u, v) in edge_faces.keys() {
+    push(&mut heap, &quadrics, &version, u, v);
+    push(&mut heap, &quadrics, &version, v, u);
+  }
+
+  let mut collapsed = vec![false; vertices.len()];
+  while num_alive > target_triangles {
+    let (_, u, v, version_u, version_v) = match heap.pop() {
+      Some(entry) => entry,
+      None => break,
+    };
+    if collapsed[u] || collapsed[v] || version[u] != version_u || version[v] != version_v {
+      continue;
+    }
+    if collapse_flips(&positions, &triangles, &alive, &vertex_faces[u], u, v) {
+      continue;
+    }
+
+    collapsed[u] = true;
+    let q = quadrics[u];
+    quadrics[v] += q;
+    for f in mem::take(&mut vertex_faces[u]) {
+      let tri = &mut triangles[f];
+      for i in tri.iter_mut() {
+        if *i == u {
+          *i = v;
+        }
+      }
+      if tri[0] == tri[1] || tri[1] == tri[2] || tri[0] == tri[2] {
+        if alive[f] {
+          alive[f] = false;
+          num_alive -= 1;
+        }
+      } else {
+        vertex_faces[v].push(f);
+      }
+    }
+    vertex_faces[v].retain(|&f| alive[f]);
+    vertex_faces[v].sort_unstable();
+    vertex_faces[v].dedup();
+    version[v] += 1;
+
+    // Every edge touching v has a new cost
+    let mut neighbors = vertex_faces[v]
+      .iter()
+      .flat_map(|&f| triangles[f].iter().cloned())
+      .filter(|&w| w != v)
+      .collect::<Vec<_>>();
+    neighbors.sort_unstable();
+    neighbors.dedup();
+    for w in neighbors {
+      push(&mut heap, &quadrics, &version, v, w);
+      push(&mut heap, &quadrics, &version, w, v);
+    }
+  }
+
+  triangles
+    .iter()
+    .zip(alive)
+    .filter(|(_, alive)| *alive)
+    .flat_map(|(tri, _)| tri.iter().map(|&i| i as u32))
+    .collect()
+}
@@ -0,0 +1,133 @@
+use crate::{
+  light::DirLight,
+  prelude::*,
+  shader::{ActiveShader, Shader},
+};
+use std::f32::consts::PI;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SkyMode {
+  Cubemap,
+
+  // Preetham sky lit by the time of day, which also moves the sun light
+  Procedural,
+}
+
+impl SkyMode {
+  pub fn toggle(self) -> Self {
+    match self {
+      SkyMode::Cubemap => SkyMode::Procedural,
+      SkyMode::Procedural => SkyMode::Cubemap,
+    }
+  }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+  let t = ((x - edge0) / (edge1 - edge0)).max(0.).min(1.);
+  t * t * (3. - 2. * t)
+}
+
+// Drives the sun across the sky. The sun rises in the east (+x) at 6:00,
+// passes south (-z) of overhead at noon and sets in the west at 18:00.
+pub struct TimeOfDay {
+  // Hours since midnight, from 0 to 24
+  pub hours: f32,
+
+  // Simulated hours per second
+  pub speed: f32,
+
+  // Angle of the sun's path away from straight overhead, like a latitude
+  pub tilt: f32,
+
+  // Haziness of the atmosphere for the sky model, about 2 for clear skies
+  // up to 10 for haze
+  pub turbidity: f32,
+}
+
+impl Default for TimeOfDay {
+  fn default() -> Self {
+    TimeOfDay {
+      hours: 7.,
+      speed: 0.25,
+      tilt: 30_f32.to_radians(),
+      turbidity: 3.,
+    }
+  }
+}
+
+impl TimeOfDay {
+  pub fn update(&mut self, dt: f32) {
+    self.advance(dt * self.speed);
+  }
+
+  // Moves the clock by hours, wrapping around midnight
+  pub fn advance(&mut self, hours: f32) {
+    self.hours = (self.hours + hours).rem_euclid(24.);
+  }
+
+  // Unit vector pointing toward the sun
+  pub fn sun_direction(&self) -> Vec3 {
+    let angle = (self.hours - 6.) / 24. * 2. * PI;
+    glm::vec3(
+      angle.cos(),
+      angle.sin() * self.tilt.cos(),
+      -angle.sin() * self.tilt.sin(),
+    )
+  }
+
+  // Sunlight for the current time: white at midday, orange and dim near the
+  // horizon, and only a faint blue ambient at night
+  pub fn sun_light(&self) -> DirLight {
+    let sun = self.sun_direction();
+    let elevation = sun.y;
+    let day = smoothstep(-0.1, 0.1, elevation);
+
+    let color = glm::lerp(
+      &glm::vec3(1., 0.45, 0.2),
+      &glm::vec3(1., 0.96, 0.9),
+      smoothstep(0., 0.5, elevation),
+    );
+    let diffuse = color * 0.8 * smoothstep(-0.05, 0.15, elevation);
+    let ambient = glm::lerp(
+      &glm::vec3(0.03, 0.04, 0.08),
+      &glm::vec3(0.2, 0.2, 0.22),
+      day,
+    );
+
+    DirLight {
+      direction: -sun,
+      ambient,
+      diffuse,
+      specular: diffuse,
+    }
+  }
+}
+
+// Analytic daylight sky drawn on the skybox cube
+pub struct ProceduralSky {
+  shader: Shader,
+}
+
+impl ProceduralSky {
+  pub async unsafe fn load(gl: &Context) -> Result<Self> {
+    let shader = Shader::load(
+      gl,
+      "assets/shaders/skybox.vert",
+      "assets/shaders/sky.frag",
+      None,
+    )
+    .await?;
+    Ok(ProceduralSky { shader })
+  }
+
+  pub fn shader(&self) -> &Shader {
+    &self.shader
+  }
+
+  pub unsafe fn bind(&self, gl: &Context, time_of_day: &TimeOfDay) -> ActiveShader {
+    let mut shader = self.shader.activate(gl);
+    shader.bind_uniform(gl, "sun_direction", &time_of_day.sun_direction());
+    shader.bind_uniform(gl, "turbidity", &time_of_day.turbidity);
+    shader
+  }
+}
@@ -0,0 +1,140 @@
+use crate::{
+  camera::Camera,
+  mesh::Mesh,
+  prelude::*,
+  scene::Scene,
+  screen_capture::Framebuffer,
+  shader::Shader,
+  texture::{TCubemap, Texture, TextureBuilder},
+};
+
+// Must match MAX_STEPS in ssr.frag
+const MAX_STEPS: i32 = 128;
+
+// Screen-space reflections. Reflected rays are marched through the depth
+// buffer in view space, and rays that leave the screen or hit nothing show
+// the skybox instead. Materials opt in with a non-zero reflectivity.
+pub struct Ssr {
+  pub enabled: bool,
+
+  // View-space distance a ray travels before giving up, and how many steps it
+  // takes, clamped to 1..=MAX_STEPS when drawn
+  pub max_distance: f32,
+  pub steps: i32,
+
+  // How far behind the depth buffer a ray can be and still count as a hit
+  pub thickness: f32,
+
+  // View-space normal in rgb and reflectivity in a
+  normals: Framebuffer,
+  normals_shader: Shader,
+
+  output: Framebuffer,
+  shader: Shader,
+  skybox: Texture<TCubemap>,
+}
+
+impl Ssr {
+  // Like Taa, the depth texture must be the one the scene is drawn with
+  pub async unsafe fn load(
+    gl: &Context,
+    width: u32,
+    height: u32,
+    depth: &Texture,
+    skybox: &Texture<TCubemap>,
+  ) -> Result<Self> {
+    let (normals_shader, shader) = try_join!(
+      Shader::load(
+        gl,
+        "assets/shaders/ssr_normals.vert",
+        "assets/shaders/ssr_normals.frag",
+        None
+      ),
+      Shader::load(
+        gl,
+        "assets/shaders/screen.vert",
+        "assets/shaders/ssr.frag",
+        None
+      )
+    )?;
+
+    let normals_texture = TextureBuilder::new(gl)
+      .with_format(glow::RGBA)
+      .with_internal_format(glow::RGBA16F)
+      .with_tex_parameter(glow::TEXTURE_MIN_FILTER, glow::NEAREST)
+      .with_tex_parameter(glow::TEXTURE_MAG_FILTER, glow::NEAREST)
+      .with_tex_parameter(glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE)
+      .with_tex_parameter(glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE)
+      .render_texture(width, height)?;
+    let normals = Framebuffer::from_textures(gl, normals_texture, Some(depth.clone()))?;
+
+    Ok(Ssr {
+      enabled: true,
+      max_distance: 8.,
+      steps: 48,
+      thickness: 0.3,
+      normals,
+      normals_shader,
+      output: Framebuffer::new(gl, width, height, false)?,
+      shader,
+      skybox: skybox.clone(),
+    })
+  }
+
+  pub fn shaders(&self) -> Vec<&Shader> {
+    vec![&self.normals_shader, &self.shader]
+  }
+
+  // Call after drawing the scene, while its depth buffer is intact
+  pub unsafe fn draw_normals(&self, gl: &Context, scene: &Scene) {
+    if !self.enabled {
+      return;
+    }
+
+    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.normals.fbo));
+    gl.clear_color(0., 0., 0., 0.);
+    gl.clear(glow::COLOR_BUFFER_BIT);
+
+    gl.depth_mask(false);
+    let mut shader = self.normals_shader.activate(gl);
+    scene.draw_opaque(gl, &mut shader);
+    gl.depth_mask(true);
+
+    gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+  }
+
+  // Adds reflections to input and returns the result, or returns input
+  // unchanged if SSR is off
+  pub unsafe fn apply<'a>(
+    &'a self,
+    gl: &Context,
+    screen_geom: &Mesh,
+    input: &'a Texture,
+    camera: &Camera,
+  ) -> &'a Texture {
+    if !self.enabled {
+      return input;
+    }
+
+    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.output.fbo));
+
+    // Invert the same (jittered) projection the depth buffer was drawn with
+    let projection = camera.jittered_projection();
+    let mut shader = self.shader.activate(gl);
+    shader.bind_uniform(gl, "screenTexture", input);
+    shader.bind_uniform(
+      gl,
+      "depthTexture",
+      self.normals.depth_texture.as_ref().unwrap(),
+    );
+    shader.bind_uniform(gl, "normalTexture", &self.normals.render_texture);
+    shader.bind_uniform(gl, "skybox", &self.skybox);
+    shader.bind_uniform(gl, "inverse_projection", &glm::inverse(&projection));
+    shader.bind_uniform(gl, "max_distance", &self.max_distance);
+    shader.bind_uniform(gl, "steps", &self.steps.clamp(1, MAX_STEPS));
+    shader.bind_uniform(gl, "thickness", &self.thickness);
+    screen_geom.draw(gl, &mut shader);
+
+    &self.output.render_texture
+  }
+}
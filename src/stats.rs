@@ -0,0 +1,116 @@
+use crate::prelude::*;
+use instant::Instant;
+use std::{
+  cell::{Cell, RefCell},
+  collections::VecDeque,
+};
+
+// Rendering work done in a frame. GL calls are spread across the whole crate,
+// so the counters live in a thread-local instead of being passed to every
+// draw function.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct FrameStats {
+  pub draw_calls: u32,
+  pub triangles: u64,
+  pub texture_binds: u32,
+  pub shader_switches: u32,
+  pub uniform_uploads: u32,
+}
+
+thread_local! {
+  static CURRENT: RefCell<FrameStats> = RefCell::new(FrameStats::default());
+  static ACTIVE_PROGRAM: Cell<Option<GlProgram>> = Cell::new(None);
+}
+
+fn update(f: impl FnOnce(&mut FrameStats)) {
+  CURRENT.with(|stats| f(&mut stats.borrow_mut()));
+}
+
+pub fn record_draw(triangles: u64) {
+  update(|stats| {
+    stats.draw_calls += 1;
+    stats.triangles += triangles;
+  });
+}
+
+pub fn record_texture_bind() {
+  update(|stats| stats.texture_binds += 1);
+}
+
+// Only counts programs that differ from the one already in use
+pub fn record_program(program: GlProgram) {
+  if ACTIVE_PROGRAM.with(|active| active.replace(Some(program))) != Some(program) {
+    update(|stats| stats.shader_switches += 1);
+  }
+}
+
+pub fn record_uniform() {
+  update(|stats| stats.uniform_uploads += 1);
+}
+
+// Returns the counts since the last call and starts over
+pub fn take() -> FrameStats {
+  CURRENT.with(|stats| std::mem::take(&mut *stats.borrow_mut()))
+}
+
+// Frames of history shown in the frame time graph and averaged for FPS
+const HISTORY: usize = 60;
+
+// Bars of increasing height for the frame time graph
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+pub struct StatsHud {
+  pub enabled: bool,
+  frame_times: VecDeque<f32>,
+  last_frame: Instant,
+  stats: FrameStats,
+}
+
+impl Default for StatsHud {
+  fn default() -> Self {
+    StatsHud {
+      enabled: false,
+      frame_times: VecDeque::new(),
+      last_frame: Instant::now(),
+      stats: FrameStats::default(),
+    }
+  }
+}
+
+impl StatsHud {
+  // Call once a frame has been drawn to collect its counters
+  pub fn end_frame(&mut self) {
+    self.stats = take();
+    let frame_time = self.last_frame.elapsed().as_nanos() as f32 / 1e6;
+    self.last_frame = Instant::now();
+    if self.frame_times.len() == HISTORY {
+      self.frame_times.pop_front();
+    }
+    self.frame_times.push_back(frame_time);
+  }
+
+  pub fn text(&self) -> String {
+    let average = self.frame_times.iter().sum::<f32>() / self.frame_times.len().max(1) as f32;
+
+    // Scale so 30 FPS is full height, or higher if frames are slower than that
+    let max = self.frame_times.iter().cloned().fold(1000. / 30., f32::max);
+    let graph = self
+      .frame_times
+      .iter()
+      .map(|ms| BARS[((ms / max) * (BARS.len() - 1) as f32).round() as usize])
+      .collect::<String>();
+
+    let stats = &self.stats;
+    format!(
+      "{:.0} FPS ({:.2} ms)\n{}\ndraw calls {}\ntriangles {}\ntexture binds {}\nshader switches {}\nuniforms {}",
+      1000. / average.max(1e-3),
+      average,
+      graph,
+      stats.draw_calls,
+      stats.triangles,
+      stats.texture_binds,
+      stats.shader_switches,
+      stats.uniform_uploads
+    )
+  }
+}
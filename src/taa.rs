@@ -0,0 +1,206 @@
+use crate::{
+  camera::Camera,
+  mesh::Mesh,
+  prelude::*,
+  scene::Scene,
+  screen_capture::Framebuffer,
+  shader::Shader,
+  texture::{Texture, TextureBuilder},
+};
+
+// Length of the jitter sequence before it repeats
+const JITTER_SAMPLES: u32 = 8;
+
+// Low-discrepancy sequence in [0, 1) used to spread jitter evenly over a pixel
+fn halton(mut index: u32, base: u32) -> f32 {
+  let mut result = 0.;
+  let mut fraction = 1.;
+  while index > 0 {
+    fraction /= base as f32;
+    result += fraction * (index % base) as f32;
+    index /= base;
+  }
+  result
+}
+
+// Temporal anti-aliasing. Each frame the camera is jittered by a sub-pixel
+// offset, then the new image is blended with the reprojected history of
+// previous frames. Also renders the motion vectors that reprojection (and
+// motion blur) rely on.
+pub struct Taa {
+  pub enabled: bool,
+
+  // Weight given to the history when blending, higher is smoother but blurrier
+  pub feedback: f32,
+
+  frame: u32,
+  texel_size: Vec2,
+
+  // Screen-space motion since the last frame. rg holds the full motion of each
+  // pixel and ba the motion of the object alone, without the camera's. Both
+  // framebuffers share the texture, but only the one for drawing objects has
+  // the scene depth attached, since the camera pass reads it.
+  velocity: Framebuffer,
+  object_velocity: Framebuffer,
+  velocity_shader: Shader,
+  camera_velocity_shader: Shader,
+
+  // Resolved frames alternate between the two targets, reading the other as history
+  history: [Framebuffer; 2],
+  current: usize,
+  history_valid: bool,
+  was_enabled: bool,
+  resolve_shader: Shader,
+}
+
+impl Taa {
+  // The depth texture must be the one the scene is drawn with, so that motion
+  // vectors are only written for visible surfaces
+  pub async unsafe fn load(gl: &Context, width: u32, height: u32, depth: &Texture) -> Result<Self> {
+    let (velocity_shader, camera_velocity_shader, resolve_shader) = try_join!(
+      Shader::load(
+        gl,
+        "assets/shaders/velocity.vert",
+        "assets/shaders/velocity.frag",
+        None
+      ),
+      Shader::load(
+        gl,
+        "assets/shaders/screen.vert",
+        "assets/shaders/camera_velocity.frag",
+        None
+      ),
+      Shader::load(
+        gl,
+        "assets/shaders/screen.vert",
+        "assets/shaders/taa.frag",
+        None
+      )
+    )?;
+
+    // Velocities are tiny fractions of the screen, so they need a float target.
+    // Nearest filtering keeps object edges from blending with the background.
+    let velocity_texture = TextureBuilder::new(gl)
+      .with_format(glow::RGBA)
+      .with_internal_format(glow::RGBA16F)
+      .with_tex_parameter(glow::TEXTURE_MIN_FILTER, glow::NEAREST)
+      .with_tex_parameter(glow::TEXTURE_MAG_FILTER, glow::NEAREST)
+      .with_tex_parameter(glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE)
+      .with_tex_parameter(glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE)
+      .render_texture(width, height)?;
+    let velocity = Framebuffer::from_textures(gl, velocity_texture.clone(), None)?;
+    let object_velocity = Framebuffer::from_textures(gl, velocity_texture, Some(depth.clone()))?;
+
+    Ok(Taa {
+      enabled: true,
+      feedback: 0.9,
+      frame: 0,
+      texel_size: glm::vec2(1. / width as f32, 1. / height as f32),
+      velocity,
+      object_velocity,
+      velocity_shader,
+      camera_velocity_shader,
+      history: [
+        Framebuffer::new(gl, width, height, false)?,
+        Framebuffer::new(gl, width, height, false)?,
+      ],
+      current: 0,
+      history_valid: false,
+      was_enabled: false,
+      resolve_shader,
+    })
+  }
+
+  pub fn shaders(&self) -> Vec<&Shader> {
+    vec![&self.velocity_shader, &self.camera_velocity_shader]
+  }
+
+  pub fn velocity_texture(&self) -> &Texture {
+    &self.velocity.render_texture
+  }
+
+  // Call before drawing the scene to pick this frame's jitter
+  pub fn begin_frame(&mut self, camera: &mut Camera) {
+    if self.enabled {
+      self.frame = self.frame.wrapping_add(1);
+      let index = self.frame % JITTER_SAMPLES + 1;
+      let offset = glm::vec2(halton(index, 2) - 0.5, halton(index, 3) - 0.5);
+
+      // One pixel is two texels wide in NDC
+      camera.jitter = offset.component_mul(&self.texel_size) * 2.;
+      self.current = 1 - self.current;
+
+      // History from before TAA was switched off would be stale
+      self.history_valid = self.was_enabled;
+    } else {
+      camera.jitter = glm::zero();
+    }
+    self.was_enabled = self.enabled;
+  }
+
+  // Call after drawing the scene, while its depth buffer is intact
+  pub unsafe fn draw_velocity(
+    &self,
+    gl: &Context,
+    screen_geom: &Mesh,
+    scene: &Scene,
+    camera: &Camera,
+  ) {
+    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.velocity.fbo));
+    gl.clear_color(0., 0., 0., 0.);
+    gl.clear(glow::COLOR_BUFFER_BIT);
+
+    // Every pixel starts with the motion caused by the camera alone, found by
+    // reprojecting its depth with the previous view-projection. Depth was
+    // drawn jittered, so it's unprojected with the jittered matrix.
+    gl.disable(glow::DEPTH_TEST);
+    let mut shader = self.camera_velocity_shader.activate(gl);
+    let depth = self.object_velocity.depth_texture.as_ref().unwrap();
+    shader.bind_uniform(gl, "depthTexture", depth);
+    shader.bind_uniform(
+      gl,
+      "inverse_view_projection",
+      &glm::inverse(&(camera.jittered_projection() * camera.view_matrix())),
+    );
+    screen_geom.draw(gl, &mut shader);
+    gl.enable(glow::DEPTH_TEST);
+
+    // Then moving objects overwrite their own pixels, matching the depth
+    // buffer exactly since they're transformed the same way as when drawn
+    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.object_velocity.fbo));
+    gl.depth_mask(false);
+    let mut shader = self.velocity_shader.activate(gl);
+    scene.draw_opaque(gl, &mut shader);
+    gl.depth_mask(true);
+
+    gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+  }
+
+  // Blends input with the history and returns the result, or returns input
+  // unchanged if TAA is off
+  pub unsafe fn resolve<'a>(
+    &'a self,
+    gl: &Context,
+    screen_geom: &Mesh,
+    input: &'a Texture,
+  ) -> &'a Texture {
+    if !self.enabled {
+      return input;
+    }
+
+    let target = &self.history[self.current];
+    let history = &self.history[1 - self.current];
+    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(target.fbo));
+
+    let mut shader = self.resolve_shader.activate(gl);
+    shader.bind_uniform(gl, "screenTexture", input);
+    shader.bind_uniform(gl, "historyTexture", &history.render_texture);
+    shader.bind_uniform(gl, "velocityTexture", self.velocity_texture());
+    shader.bind_uniform(gl, "texel_size", &self.texel_size);
+    shader.bind_uniform(gl, "feedback", &self.feedback);
+    shader.bind_uniform(gl, "history_valid", &self.history_valid);
+    screen_geom.draw(gl, &mut shader);
+
+    &target.render_texture
+  }
+}
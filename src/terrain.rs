@@ -0,0 +1,152 @@
+use crate::{
+  camera::Frustum,
+  geometry::Geometry,
+  io,
+  material::Material,
+  mesh::Mesh,
+  prelude::*,
+  shader::{ActiveShader, Shader},
+  texture::{Texture, TextureBuilder},
+};
+use std::{path::Path, rc::Rc};
+
+// Layers the terrain shader can blend, one per splat map channel
+pub const MAX_LAYERS: usize = 4;
+
+pub struct TerrainConfig {
+  // Extent on x and z, centered on the origin
+  pub size: Vec2,
+
+  // Height of a white heightmap pixel
+  pub height_scale: f32,
+
+  // Heightmap pixels along each side of a chunk
+  pub chunk_cells: u32,
+
+  // World units covered by one repeat of the layer textures
+  pub tile_size: f32,
+}
+
+impl Default for TerrainConfig {
+  fn default() -> Self {
+    TerrainConfig {
+      size: glm::vec2(64., 64.),
+      height_scale: 8.,
+      chunk_cells: 32,
+      tile_size: 4.,
+    }
+  }
+}
+
+struct Chunk {
+  mesh: Mesh,
+
+  // Bounds in terrain space, for culling
+  min: Vec3,
+  max: Vec3,
+}
+
+// Heightmap terrain split into square chunks, each culled against the view
+// frustum, with up to MAX_LAYERS materials blended by a splat map
+pub struct Terrain {
+  pub transform: Mat4,
+  config: TerrainConfig,
+  chunks: Vec<Chunk>,
+  layers: Vec<Material>,
+  splat_map: Texture,
+  shader: Shader,
+}
+
+impl Terrain {
+  pub async unsafe fn load(
+    gl: &Context,
+    heightmap_path: impl AsRef<Path>,
+    splat_map_path: impl AsRef<Path>,
+    layers: Vec<Material>,
+    config: TerrainConfig,
+    transform: Mat4,
+  ) -> Result<Self> {
+    if layers.is_empty() || layers.len() > MAX_LAYERS {
+      bail!(
+        "Terrain needs 1 to {} layers but got {}",
+        MAX_LAYERS,
+        layers.len()
+      );
+    }
+
+    let (heightmap, splat_map, shader) = try_join!(
+      io::load_image(heightmap_path),
+      TextureBuilder::new(gl)
+        .with_tex_parameter(glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE)
+        .with_tex_parameter(glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE)
+        .load(splat_map_path),
+      Shader::load(
+        gl,
+        "assets/shaders/terrain.vert",
+        "assets/shaders/terrain.frag",
+        None
+      )
+    )?;
+
+    let heightmap = Rc::new(heightmap.into_luma8());
+    let (width, height) = heightmap.dimensions();
+    if width < 2 || height < 2 {
+      bail!("Heightmap must be at least 2x2 pixels");
+    }
+    let chunk_cells = config.chunk_cells.max(1);
+
+    let mut chunks = vec![];
+    for z in (0..height - 1).step_by(chunk_cells as usize) {
+      for x in (0..width - 1).step_by(chunk_cells as usize) {
+        let mesh = Geometry::Terrain {
+          heightmap: heightmap.clone(),
+          size: config.size,
+          height_scale: config.height_scale,
+          x_range: x..(x + chunk_cells).min(width - 1),
+          z_range: z..(z + chunk_cells).min(height - 1),
+        }
+        .to_mesh(gl, None)?;
+
+        let (min, max) = mesh.vertices.iter().fold(
+          (Vec3::repeat(f32::MAX), Vec3::repeat(f32::MIN)),
+          |(min, max), vertex| {
+            (
+              glm::min2(&min, &vertex.position),
+              glm::max2(&max, &vertex.position),
+            )
+          },
+        );
+        chunks.push(Chunk { mesh, min, max });
+      }
+    }
+
+    Ok(Terrain {
+      transform,
+      config,
+      chunks,
+      layers,
+      splat_map,
+      shader,
+    })
+  }
+
+  pub fn shader(&self) -> &Shader {
+    &self.shader
+  }
+
+  // Draws the chunks inside the frustum of view_projection with the terrain
+  // shader, which must already be active with lights bound
+  pub unsafe fn draw(&self, gl: &Context, shader: &mut ActiveShader, view_projection: &Mat4) {
+    shader.bind_uniform(gl, "model", &self.transform);
+    shader.bind_uniform(gl, "layers", &self.layers);
+    shader.bind_uniform(gl, "splat_map", &self.splat_map);
+    shader.bind_uniform(gl, "tiling", &(self.config.size / self.config.tile_size));
+
+    let frustum = Frustum::from_matrix(&(view_projection * self.transform));
+    for chunk in &self.chunks {
+      if frustum.intersects_aabb(&chunk.min, &chunk.max) {
+        chunk.mesh.draw(gl, shader);
+      }
+    }
+  }
+}
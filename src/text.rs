@@ -7,6 +7,7 @@ use crate::{
   io,
   prelude::*,
   shader::Shader,
+  stats,
   texture::{Texture, TextureBuilder},
 };
 use std::{collections::HashMap, mem::size_of, path::Path};
@@ -171,6 +172,7 @@ impl Font {
 
     // Instanced means that the vertex shader is run 4 times for each vertex (glyph location)
     gl.draw_arrays_instanced(glow::TRIANGLE_STRIP, 0, 4, self.vertex_count as i32);
+    stats::record_draw(self.vertex_count as u64 * 2);
 
     Ok(())
   }
@@ -4,6 +4,7 @@ use crate::{
   io,
   prelude::*,
   shader::{ActiveShader, BindUniform},
+  stats,
 };
 use futures::future::try_join_all;
 use image::{DynamicImage, GenericImageView};
@@ -33,6 +34,7 @@ pub struct TextureBuilder<'a, Target> {
   tex_parameters: HashMap<u32, u32>,
   flip: bool,
   format: u32,
+  internal_format: Option<u32>,
   alignment: u32,
   _marker: PhantomData<Target>,
 }
@@ -48,6 +50,7 @@ impl<'a> TextureBuilder<'a, T2d> {
       },
       flip: true,
       format: glow::RGBA,
+      internal_format: None,
       alignment: 4,
       _marker: PhantomData,
       gl,
@@ -161,6 +164,12 @@ impl<'a, Target: TextureTarget> TextureBuilder<'a, Target> {
     self
   }
 
+  // Overrides the sized format picked from the pixel format, e.g. for float targets
+  pub fn with_internal_format(mut self, internal_format: u32) -> Self {
+    self.internal_format = Some(internal_format);
+    self
+  }
+
   pub fn with_alignment(mut self, alignment: u32) -> Self {
     self.alignment = alignment;
     self
@@ -183,6 +192,7 @@ impl<'a, Target: TextureTarget> TextureBuilder<'a, Target> {
       tex_parameters,
       flip,
       format,
+      internal_format,
       alignment,
       ..
     } = self;
@@ -191,19 +201,32 @@ impl<'a, Target: TextureTarget> TextureBuilder<'a, Target> {
       tex_parameters,
       flip,
       format,
+      internal_format,
       alignment,
       _marker: PhantomData,
     }
   }
 
   fn internal_format(&self) -> u32 {
+    if let Some(internal_format) = self.internal_format {
+      return internal_format;
+    }
     match self.format {
       glow::RGB | glow::RGBA => self.format,
       glow::RED => glow::R8,
+      glow::DEPTH_STENCIL => glow::DEPTH24_STENCIL8,
       _ => unimplemented!(),
     }
   }
 
+  fn data_type(&self) -> u32 {
+    match (self.format, self.internal_format()) {
+      (glow::DEPTH_STENCIL, _) => glow::UNSIGNED_INT_24_8,
+      (_, glow::R16F) | (_, glow::RG16F) | (_, glow::RGBA16F) => glow::FLOAT,
+      _ => glow::UNSIGNED_BYTE,
+    }
+  }
+
   fn target() -> u32 {
     Target::TARGET
   }
@@ -243,7 +266,7 @@ impl<'a, Target: TextureTarget> TextureBuilder<'a, Target> {
       height as i32,
       0,
       self.format,
-      glow::UNSIGNED_BYTE,
+      self.data_type(),
       None,
     );
 
@@ -300,5 +323,6 @@ impl<Target: TextureTarget> BindUniform for Texture<Target> {
     let gl_unit = glow::TEXTURE0 + unit;
     gl.active_texture(gl_unit);
     gl.bind_texture(Target::TARGET, Some(self.texture));
+    stats::record_texture_bind();
   }
 }
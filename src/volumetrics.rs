@@ -0,0 +1,189 @@
+use crate::{
+  camera::Camera,
+  light::{DirLight, SpotLight},
+  mesh::Mesh,
+  prelude::*,
+  scene::Scene,
+  screen_capture::Framebuffer,
+  shader::Shader,
+  texture::Texture,
+};
+
+// Must match MAX_SAMPLES in god_rays.frag and MAX_STEPS in spot_fog.frag
+const MAX_SAMPLES: i32 = 128;
+const MAX_STEPS: i32 = 128;
+
+// Light scattered toward the camera by air. God rays blur the sky outward
+// from the sun's position on screen, so geometry in front of the sun casts
+// shafts. Spot light fog marches each pixel's view ray through the spot
+// lights' cones.
+pub struct Volumetrics {
+  pub god_rays: bool,
+
+  // Fraction of the way to the sun the god ray samples cover
+  pub density: f32,
+
+  // Falloff of each sample's contribution along the way to the sun
+  pub decay: f32,
+  pub weight: f32,
+  pub exposure: f32,
+
+  // Clamped to 1..=MAX_SAMPLES when drawn
+  pub samples: i32,
+
+  pub spot_fog: bool,
+
+  // Scattering per world unit, and view rays are cut off at max_distance
+  pub fog_density: f32,
+
+  // Clamped to 1..=MAX_STEPS when drawn
+  pub fog_steps: i32,
+  pub max_distance: f32,
+
+  // Lights as of the last update_lights
+  sun: Option<DirLight>,
+  spot_lights: Vec<SpotLight>,
+
+  depth: Texture,
+  god_rays_output: Framebuffer,
+  god_rays_shader: Shader,
+  fog_output: Framebuffer,
+  fog_shader: Shader,
+}
+
+impl Volumetrics {
+  // Like Ssr, the depth texture must be the one the scene is drawn with
+  pub async unsafe fn load(gl: &Context, width: u32, height: u32, depth: &Texture) -> Result<Self> {
+    let (god_rays_shader, fog_shader) = try_join!(
+      Shader::load(
+        gl,
+        "assets/shaders/screen.vert",
+        "assets/shaders/god_rays.frag",
+        None
+      ),
+      Shader::load(
+        gl,
+        "assets/shaders/screen.vert",
+        "assets/shaders/spot_fog.frag",
+        None
+      )
+    )?;
+
+    Ok(Volumetrics {
+      god_rays: true,
+      density: 0.9,
+      decay: 0.96,
+      weight: 0.4,
+      exposure: 0.25,
+      samples: 64,
+      spot_fog: true,
+      fog_density: 0.15,
+      fog_steps: 32,
+      max_distance: 30.,
+      sun: None,
+      spot_lights: vec![],
+      depth: depth.clone(),
+      god_rays_output: Framebuffer::new(gl, width, height, false)?,
+      god_rays_shader,
+      fog_output: Framebuffer::new(gl, width, height, false)?,
+      fog_shader,
+    })
+  }
+
+  pub fn shaders(&self) -> Vec<&Shader> {
+    vec![&self.god_rays_shader, &self.fog_shader]
+  }
+
+  // Takes the first directional light as the sun
+  pub fn update_lights(&mut self, scene: &Scene) {
+    self.sun = scene.dir_lights().first().cloned();
+    self.spot_lights = scene.spot_lights().to_vec();
+  }
+
+  // Adds scattered light to input and returns the result, or returns input
+  // unchanged if both effects are off
+  pub unsafe fn apply<'a>(
+    &'a self,
+    gl: &Context,
+    screen_geom: &Mesh,
+    input: &'a Texture,
+    camera: &Camera,
+  ) -> &'a Texture {
+    let input = self.apply_spot_fog(gl, screen_geom, input, camera);
+    self.apply_god_rays(gl, screen_geom, input, camera)
+  }
+
+  unsafe fn apply_spot_fog<'a>(
+    &'a self,
+    gl: &Context,
+    screen_geom: &Mesh,
+    input: &'a Texture,
+    camera: &Camera,
+  ) -> &'a Texture {
+    if !self.spot_fog || self.spot_lights.is_empty() {
+      return input;
+    }
+
+    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.fog_output.fbo));
+
+    // There are no shadow maps yet, so cones are lit all the way through
+    // anything inside them
+    let mut shader = self.fog_shader.activate(gl);
+    shader.bind_uniform(gl, "screenTexture", input);
+    shader.bind_uniform(gl, "depthTexture", &self.depth);
+    shader.bind_uniform(
+      gl,
+      "inverse_projection",
+      &glm::inverse(&camera.jittered_projection()),
+    );
+    shader.bind_uniform(gl, "inverse_view", &glm::inverse(&camera.view_matrix()));
+    shader.bind_uniform(gl, "spot_lights", &self.spot_lights);
+    shader.bind_uniform(gl, "fog_density", &self.fog_density);
+    shader.bind_uniform(gl, "steps", &self.fog_steps.clamp(1, MAX_STEPS));
+    shader.bind_uniform(gl, "max_distance", &self.max_distance);
+    screen_geom.draw(gl, &mut shader);
+
+    &self.fog_output.render_texture
+  }
+
+  unsafe fn apply_god_rays<'a>(
+    &'a self,
+    gl: &Context,
+    screen_geom: &Mesh,
+    input: &'a Texture,
+    camera: &Camera,
+  ) -> &'a Texture {
+    let sun = match self.sun.as_ref() {
+      Some(sun) if self.god_rays => sun,
+      _ => return input,
+    };
+
+    // The sun is infinitely far away, so project its direction with w = 0.
+    // Behind the camera there are no rays to draw.
+    let direction = -glm::normalize(&sun.direction);
+    let clip = camera.view_projection() * glm::vec4(direction.x, direction.y, direction.z, 0.);
+    if clip.w <= 0. {
+      return input;
+    }
+    let sun_position = glm::vec2(clip.x / clip.w, clip.y / clip.w) * 0.5 + glm::vec2(0.5, 0.5);
+
+    // Fade out as the sun leaves the screen, where its rays would cut off
+    let offscreen = (sun_position - glm::vec2(0.5, 0.5)).abs().max() * 2.;
+    let fade = (1.5 - offscreen).max(0.).min(1.);
+
+    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.god_rays_output.fbo));
+    let mut shader = self.god_rays_shader.activate(gl);
+    shader.bind_uniform(gl, "screenTexture", input);
+    shader.bind_uniform(gl, "depthTexture", &self.depth);
+    shader.bind_uniform(gl, "sun_position", &sun_position);
+    shader.bind_uniform(gl, "sun_color", &(sun.diffuse * fade));
+    shader.bind_uniform(gl, "density", &self.density);
+    shader.bind_uniform(gl, "decay", &self.decay);
+    shader.bind_uniform(gl, "weight", &self.weight);
+    shader.bind_uniform(gl, "exposure", &self.exposure);
+    shader.bind_uniform(gl, "samples", &self.samples.clamp(1, MAX_SAMPLES));
+    screen_geom.draw(gl, &mut shader);
+
+    &self.god_rays_output.render_texture
+  }
+}
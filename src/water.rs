@@ -0,0 +1,257 @@
+use crate::{
+  camera::Camera,
+  geometry::Geometry,
+  mesh::Mesh,
+  prelude::*,
+  screen_capture::Framebuffer,
+  shader::{ActiveShader, Shader},
+  texture::{Texture, TextureBuilder},
+};
+
+// Must match MAX_WAVES in water.vert
+pub const MAX_WAVES: usize = 8;
+
+// One Gerstner wave. Its speed follows from the wavelength as in deep water.
+#[derive(BindUniform, ShaderTypeDef, Clone, Copy)]
+pub struct Wave {
+  pub direction: Vec2,
+
+  // 0 for a sine wave up to 1 for sharp crests. The steepness of all waves
+  // should sum to at most 1, or crests loop over themselves.
+  pub steepness: f32,
+  pub wavelength: f32,
+}
+
+pub struct WaterConfig {
+  // Extent on x and z, centered on position
+  pub size: Vec2,
+  pub position: Vec3,
+
+  // Quads along each side of the surface mesh
+  pub cells: u32,
+
+  // At most MAX_WAVES
+  pub waves: Vec<Wave>,
+
+  // Color looking straight down into shallow water and through deep water
+  pub shallow_color: Vec3,
+  pub deep_color: Vec3,
+
+  // Distance through the water at which it is fully deep_color
+  pub depth_falloff: f32,
+
+  // World units covered by one repeat of the normal map
+  pub normal_tile_size: f32,
+
+  // How far the normal map bends reflections and refractions, in screen UV
+  pub distortion: f32,
+
+  // Resolution of the reflection and refraction targets. They're sampled in
+  // screen space, so they needn't match the screen's aspect ratio.
+  pub target_size: (u32, u32),
+}
+
+impl Default for WaterConfig {
+  fn default() -> Self {
+    WaterConfig {
+      size: glm::vec2(16., 16.),
+      position: glm::zero(),
+      cells: 128,
+      waves: vec![
+        Wave {
+          direction: glm::vec2(1., 0.3),
+          steepness: 0.2,
+          wavelength: 6.,
+        },
+        Wave {
+          direction: glm::vec2(-0.4, 1.),
+          steepness: 0.15,
+          wavelength: 3.1,
+        },
+        Wave {
+          direction: glm::vec2(0.7, -0.8),
+          steepness: 0.1,
+          wavelength: 1.7,
+        },
+      ],
+      shallow_color: glm::vec3(0.1, 0.45, 0.45),
+      deep_color: glm::vec3(0.02, 0.1, 0.2),
+      depth_falloff: 2.,
+      normal_tile_size: 4.,
+      distortion: 0.02,
+      target_size: (640, 360),
+    }
+  }
+}
+
+// Replaces the near plane of projection with a world space plane, so only
+// what's on the side the plane's normal points to gets drawn. The camera
+// must be on the other side. (Lengyel, "Oblique View Frustum Depth Projection
+// and Clipping")
+fn oblique_projection(projection: &Mat4, view: &Mat4, plane: &Vec4) -> Mat4 {
+  let inverse_view = view.try_inverse().unwrap_or_else(Mat4::identity);
+  let clip_plane = inverse_view.transpose() * plane;
+  let inverse_projection = projection.try_inverse().unwrap_or_else(Mat4::identity);
+
+  // Corner of the frustum opposite the plane
+  let corner = inverse_projection * glm::vec4(clip_plane.x.signum(), clip_plane.y.signum(), 1., 1.);
+  let scaled = clip_plane * (2. / clip_plane.dot(&corner));
+
+  let mut oblique = *projection;
+  oblique.set_row(2, &(scaled - projection.row(3).transpose()).transpose());
+  oblique
+}
+
+// Water surface displaced by Gerstner waves on the GPU. Reflections come from
+// rendering the scene with a camera mirrored under the surface, and
+// refraction from rendering it again with depth, which tints the water by how
+// far light travels through it.
+pub struct Water {
+  config: WaterConfig,
+  mesh: Mesh,
+  shader: Shader,
+  normal_map: Texture,
+  reflection: Framebuffer,
+  refraction: Framebuffer,
+  width: u32,
+  height: u32,
+}
+
+impl Water {
+  pub async unsafe fn load(gl: &Context, config: WaterConfig) -> Result<Self> {
+    if config.waves.len() > MAX_WAVES {
+      bail!(
+        "Water has {} waves, max is {}",
+        config.waves.len(),
+        MAX_WAVES
+      );
+    }
+
+    let (shader, normal_map) = try_join!(
+      Shader::load(
+        gl,
+        "assets/shaders/water.vert",
+        "assets/shaders/water.frag",
+        None
+      ),
+      TextureBuilder::new(gl).load("assets/textures/water_normal.png")
+    )?;
+
+    let mesh = Geometry::Grid {
+      length: config.size.x,
+      width: config.size.y,
+      cells: config.cells,
+    }
+    .to_mesh(gl, None)?;
+
+    let (width, height) = config.target_size;
+    let reflection = Framebuffer::new(gl, width, height, true)?;
+    let refraction = Framebuffer::new(gl, width, height, true)?;
+
+    Ok(Water {
+      config,
+      mesh,
+      shader,
+      normal_map,
+      reflection,
+      refraction,
+      width,
+      height,
+    })
+  }
+
+  pub fn shader(&self) -> &Shader {
+    &self.shader
+  }
+
+  // Camera mirrored below the surface, clipped to draw only what's above it
+  pub fn reflection_camera(&self, camera: &Camera) -> Camera {
+    let level = self.config.position.y;
+    let mut mirrored = camera.clone();
+    mirrored.pos.y = 2. * level - camera.pos.y;
+    mirrored.pitch = -camera.pitch;
+    mirrored.jitter = glm::zero();
+
+    // Clipping only works from under the plane, so from below the surface
+    // reflections just show everything
+    if mirrored.pos.y < level {
+      let plane = glm::vec4(0., 1., 0., -level);
+      mirrored.projection = oblique_projection(&camera.projection, &mirrored.view_matrix(), &plane);
+    }
+    mirrored
+  }
+
+  // Camera clipped to draw only what's under the surface
+  pub fn refraction_camera(&self, camera: &Camera) -> Camera {
+    let level = self.config.position.y;
+    let mut clipped = camera.clone();
+    clipped.jitter = glm::zero();
+
+    // As with reflections, the plane faces away from the camera, so from
+    // below the surface refraction shows everything
+    if camera.pos.y > level {
+      let plane = glm::vec4(0., -1., 0., level);
+      clipped.projection = oblique_projection(&camera.projection, &camera.view_matrix(), &plane);
+    }
+    clipped
+  }
+
+  // Renders into the reflection and refraction targets, each with draw_scene
+  // given the camera to draw from. Leaves the default framebuffer bound and
+  // the viewport at the targets' size.
+  pub unsafe fn draw_passes(
+    &self,
+    gl: &Context,
+    camera: &Camera,
+    mut draw_scene: impl FnMut(&Camera),
+  ) {
+    gl.viewport(0, 0, self.width as i32, self.height as i32);
+
+    let passes = [
+      (&self.reflection, self.reflection_camera(camera)),
+      (&self.refraction, self.refraction_camera(camera)),
+    ];
+    for (framebuffer, camera) in passes.iter() {
+      gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer.fbo));
+      gl.clear_color(0.1, 0.1, 0.1, 1.0);
+      gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT | glow::STENCIL_BUFFER_BIT);
+      draw_scene(camera);
+    }
+
+    gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+  }
+
+  // Draws the surface with the water shader, which must already be active
+  // with lights bound
+  pub unsafe fn draw(&self, gl: &Context, shader: &mut ActiveShader, camera: &Camera, time: f32) {
+    let (near, far) = camera.near_far();
+    let config = &self.config;
+    shader.bind_uniform(gl, "model", &glm::translation(&config.position));
+    shader.bind_uniform(gl, "time", &time);
+    shader.bind_uniform(gl, "waves", &config.waves);
+    shader.bind_uniform(gl, "reflection_texture", &self.reflection.render_texture);
+    shader.bind_uniform(gl, "refraction_texture", &self.refraction.render_texture);
+    shader.bind_uniform(
+      gl,
+      "refraction_depth",
+      self.refraction.depth_texture.as_ref().unwrap(),
+    );
+    // The oblique clip plane bends the refraction's depth, so it's unprojected
+    // with the projection it was drawn with rather than from near and far
+    let refraction_projection = self.refraction_camera(camera).projection;
+    shader.bind_uniform(
+      gl,
+      "refraction_inverse_projection",
+      &glm::inverse(&refraction_projection),
+    );
+    shader.bind_uniform(gl, "normal_map", &self.normal_map);
+    shader.bind_uniform(gl, "near", &near);
+    shader.bind_uniform(gl, "far", &far);
+    shader.bind_uniform(gl, "shallow_color", &config.shallow_color);
+    shader.bind_uniform(gl, "deep_color", &config.deep_color);
+    shader.bind_uniform(gl, "depth_falloff", &config.depth_falloff);
+    shader.bind_uniform(gl, "normal_tile_size", &config.normal_tile_size);
+    shader.bind_uniform(gl, "distortion", &config.distortion);
+    self.mesh.draw(gl, shader);
+  }
+}
@@ -0,0 +1,13 @@
+// Unlit diffuse texture, to check texture loading and UVs in isolation
+in vec3 Normal;
+in vec3 FragPos;
+in vec2 TexCoords;
+
+uniform Material material;
+
+out vec4 FragColor;
+
+void main()
+{
+  FragColor = texture(material.diffuse, TexCoords);
+}